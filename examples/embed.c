@@ -0,0 +1,67 @@
+/*
+ * Runs a ROM for a few seconds through the C API and prints the last frame in the terminal.
+ *
+ *   cargo rustc --release --lib --crate-type cdylib --no-default-features --features ffi
+ *   cc -Iinclude examples/embed.c -Ltarget/release -lchip_huit -o embed
+ *   LD_LIBRARY_PATH=target/release ./embed rom.ch8
+ */
+#include <stdio.h>
+#include <stdlib.h>
+
+#include "chip_huit.h"
+
+int main(int argc, char **argv) {
+    if (argc != 2) {
+        fprintf(stderr, "usage: %s <rom>\n", argv[0]);
+        return 2;
+    }
+
+    FILE *file = fopen(argv[1], "rb");
+    if (file == NULL) {
+        perror(argv[1]);
+        return 1;
+    }
+    uint8_t rom[65536];
+    size_t size = fread(rom, 1, sizeof(rom), file);
+    fclose(file);
+
+    Chip8Emulator *emulator = chip8_new();
+    if (chip8_load_rom(emulator, rom, size) != 0) {
+        fprintf(stderr, "error: %s\n", chip8_last_error(emulator));
+        chip8_free(emulator);
+        return 1;
+    }
+    chip8_set_seed(emulator, 1);
+
+    for (int frame = 0; frame < 180; frame++) {
+        chip8_step_frame(emulator);
+    }
+
+    /* the state taken now gives the same frames when loaded back */
+    size_t state_size = chip8_state_size(emulator);
+    uint8_t *state = (uint8_t *)malloc(state_size);
+    state_size = chip8_save_state(emulator, state, state_size);
+    for (int frame = 0; frame < 60; frame++) {
+        chip8_step_frame(emulator);
+    }
+    if (chip8_load_state(emulator, state, state_size) != 0) {
+        fprintf(stderr, "error: %s\n", chip8_last_error(emulator));
+    }
+    free(state);
+
+    size_t width = chip8_width(emulator);
+    size_t height = chip8_height(emulator);
+    const uint8_t *pixels = chip8_framebuffer(emulator);
+    for (size_t y = 0; y < height; y += 2) {
+        for (size_t x = 0; x < width; x++) {
+            /* two rows per line, a pixel is lit when it is brighter than grey */
+            int top = pixels[(y * width + x) * 4] > 0x80;
+            int bottom = pixels[((y + 1) * width + x) * 4] > 0x80;
+            fputs(top ? (bottom ? "█" : "▀") : (bottom ? "▄" : " "), stdout);
+        }
+        putchar('\n');
+    }
+
+    chip8_free(emulator);
+    return 0;
+}
@@ -0,0 +1,284 @@
+//! Runs a ROM in the libretro core like a frontend would, without window nor sound,
+//! and checks the frames, the sound and the save states.
+//!
+//! ```text
+//! cargo rustc --lib --crate-type cdylib --no-default-features --features libretro
+//! cargo run --example libretro_harness --features libretro -- target/debug/libchip_huit.so rom.ch8
+//! ```
+
+use std::env;
+use std::ffi::CStr;
+use std::fs;
+use std::os::raw::{c_char, c_uint, c_void};
+use std::process;
+use std::ptr;
+use std::sync::{Mutex, OnceLock};
+
+use libloading::Library;
+
+const RETRO_ENVIRONMENT_SET_PIXEL_FORMAT: c_uint = 10;
+const RETRO_PIXEL_FORMAT_XRGB8888: c_uint = 1;
+const RETRO_DEVICE_JOYPAD: c_uint = 1;
+const RETRO_DEVICE_ID_JOYPAD_RIGHT: c_uint = 7;
+const RETRO_MEMORY_SYSTEM_RAM: c_uint = 2;
+
+/// Frames run before and after the save state.
+const FRAMES: u32 = 300;
+
+#[repr(C)]
+struct SystemInfo {
+    library_name: *const c_char,
+    library_version: *const c_char,
+    valid_extensions: *const c_char,
+    need_fullpath: bool,
+    block_extract: bool,
+}
+
+#[repr(C)]
+struct AvInfo {
+    base_width: c_uint,
+    base_height: c_uint,
+    max_width: c_uint,
+    max_height: c_uint,
+    aspect_ratio: f32,
+    fps: f64,
+    sample_rate: f64,
+}
+
+#[repr(C)]
+struct GameInfo {
+    path: *const c_char,
+    data: *const c_void,
+    size: usize,
+    meta: *const c_char,
+}
+
+type Environment = unsafe extern "C" fn(c_uint, *mut c_void) -> bool;
+type VideoRefresh = unsafe extern "C" fn(*const c_void, c_uint, c_uint, usize);
+type AudioSample = unsafe extern "C" fn(i16, i16);
+type AudioSampleBatch = unsafe extern "C" fn(*const i16, usize) -> usize;
+type InputPoll = unsafe extern "C" fn();
+type InputState = unsafe extern "C" fn(c_uint, c_uint, c_uint, c_uint) -> i16;
+
+/// What the core gave since the last reset.
+struct Output {
+    frames: u32,
+    audio_frames: usize,
+    /// FNV-1a of every frame and sample, in order.
+    hash: u64,
+    pixel_format: Option<c_uint>,
+}
+
+static OUTPUT: Mutex<Output> = Mutex::new(Output {
+    frames: 0,
+    audio_frames: 0,
+    hash: 0xcbf29ce484222325,
+    pixel_format: None,
+});
+
+/// Called back from the video callback, like the frontends reading the memory for
+/// the achievements.
+static GET_MEMORY_SIZE: OnceLock<unsafe extern "C" fn(c_uint) -> usize> = OnceLock::new();
+
+impl Output {
+    fn hash(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.hash = (self.hash ^ byte as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.frames = 0;
+        self.audio_frames = 0;
+        self.hash = 0xcbf29ce484222325;
+    }
+}
+
+unsafe extern "C" fn environment(cmd: c_uint, data: *mut c_void) -> bool {
+    if cmd == RETRO_ENVIRONMENT_SET_PIXEL_FORMAT {
+        OUTPUT.lock().unwrap().pixel_format = Some(*(data as *const c_uint));
+        return true;
+    }
+    false
+}
+
+unsafe extern "C" fn video_refresh(
+    data: *const c_void,
+    width: c_uint,
+    height: c_uint,
+    pitch: usize,
+) {
+    let mut output = OUTPUT.lock().unwrap();
+    output.frames += 1;
+    if let Some(get_memory_size) = GET_MEMORY_SIZE.get() {
+        get_memory_size(RETRO_MEMORY_SYSTEM_RAM);
+    }
+    if !data.is_null() {
+        for y in 0..height as usize {
+            let row = (data as *const u8).add(y * pitch);
+            output.hash(std::slice::from_raw_parts(row, width as usize * 4));
+        }
+    }
+}
+
+unsafe extern "C" fn audio_sample(_left: i16, _right: i16) {}
+
+unsafe extern "C" fn audio_sample_batch(data: *const i16, frames: usize) -> usize {
+    let mut output = OUTPUT.lock().unwrap();
+    output.audio_frames += frames;
+    let samples = std::slice::from_raw_parts(data as *const u8, frames * 4);
+    output.hash(samples);
+    frames
+}
+
+unsafe extern "C" fn input_poll() {}
+
+/// Holds right for 20 frames out of 40, to move something in most games.
+unsafe extern "C" fn input_state(port: c_uint, device: c_uint, _index: c_uint, id: c_uint) -> i16 {
+    let frames = OUTPUT.lock().unwrap().frames;
+    let is_pressed = port == 0
+        && device == RETRO_DEVICE_JOYPAD
+        && id == RETRO_DEVICE_ID_JOYPAD_RIGHT
+        && frames % 40 < 20;
+    is_pressed as i16
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 {
+        eprintln!("usage: {} <core.so> <rom>", args[0]);
+        process::exit(2);
+    }
+    if let Err(error) = unsafe { run(&args[1], &args[2]) } {
+        eprintln!("error: {}", error);
+        process::exit(1);
+    }
+}
+
+unsafe fn run(core_path: &str, rom_path: &str) -> Result<(), String> {
+    let rom = fs::read(rom_path).map_err(|error| format!("cannot read {}: {}", rom_path, error))?;
+    let core =
+        Library::new(core_path).map_err(|error| format!("cannot load the core: {}", error))?;
+
+    macro_rules! function {
+        ($name:ident: $type:ty) => {
+            let $name = *core
+                .get::<$type>(concat!(stringify!($name), "\0").as_bytes())
+                .map_err(|error| format!("{}: {}", stringify!($name), error))?;
+        };
+    }
+    function!(retro_api_version: unsafe extern "C" fn() -> c_uint);
+    function!(retro_get_system_info: unsafe extern "C" fn(*mut SystemInfo));
+    function!(retro_get_system_av_info: unsafe extern "C" fn(*mut AvInfo));
+    function!(retro_set_environment: unsafe extern "C" fn(Environment));
+    function!(retro_set_video_refresh: unsafe extern "C" fn(VideoRefresh));
+    function!(retro_set_audio_sample: unsafe extern "C" fn(AudioSample));
+    function!(retro_set_audio_sample_batch: unsafe extern "C" fn(AudioSampleBatch));
+    function!(retro_set_input_poll: unsafe extern "C" fn(InputPoll));
+    function!(retro_set_input_state: unsafe extern "C" fn(InputState));
+    function!(retro_init: unsafe extern "C" fn());
+    function!(retro_deinit: unsafe extern "C" fn());
+    function!(retro_load_game: unsafe extern "C" fn(*const GameInfo) -> bool);
+    function!(retro_unload_game: unsafe extern "C" fn());
+    function!(retro_run: unsafe extern "C" fn());
+    function!(retro_serialize_size: unsafe extern "C" fn() -> usize);
+    function!(retro_serialize: unsafe extern "C" fn(*mut c_void, usize) -> bool);
+    function!(retro_unserialize: unsafe extern "C" fn(*const c_void, usize) -> bool);
+    function!(retro_get_memory_size: unsafe extern "C" fn(c_uint) -> usize);
+
+    if retro_api_version() != 1 {
+        return Err(format!(
+            "the core has the API version {}",
+            retro_api_version()
+        ));
+    }
+    let mut info: SystemInfo = std::mem::zeroed();
+    retro_get_system_info(&mut info);
+    println!(
+        "{} {} ({})",
+        CStr::from_ptr(info.library_name).to_string_lossy(),
+        CStr::from_ptr(info.library_version).to_string_lossy(),
+        CStr::from_ptr(info.valid_extensions).to_string_lossy()
+    );
+
+    let _ = GET_MEMORY_SIZE.set(retro_get_memory_size);
+    retro_set_environment(environment);
+    retro_set_video_refresh(video_refresh);
+    retro_set_audio_sample(audio_sample);
+    retro_set_audio_sample_batch(audio_sample_batch);
+    retro_set_input_poll(input_poll);
+    retro_set_input_state(input_state);
+    retro_init();
+
+    let game = GameInfo {
+        path: ptr::null(),
+        data: rom.as_ptr() as *const c_void,
+        size: rom.len(),
+        meta: ptr::null(),
+    };
+    if !retro_load_game(&game) {
+        return Err("the core didn't load the game".to_string());
+    }
+    if OUTPUT.lock().unwrap().pixel_format != Some(RETRO_PIXEL_FORMAT_XRGB8888) {
+        return Err("the core didn't ask for the XRGB8888 pixel format".to_string());
+    }
+    let mut av_info: AvInfo = std::mem::zeroed();
+    retro_get_system_av_info(&mut av_info);
+    println!(
+        "{}x{} up to {}x{}, {} fps, {} Hz, {} bytes of memory",
+        av_info.base_width,
+        av_info.base_height,
+        av_info.max_width,
+        av_info.max_height,
+        av_info.fps,
+        av_info.sample_rate,
+        retro_get_memory_size(RETRO_MEMORY_SYSTEM_RAM)
+    );
+
+    for _ in 0..FRAMES {
+        retro_run();
+    }
+    let mut state = vec![0u8; retro_serialize_size()];
+    if !retro_serialize(state.as_mut_ptr() as *mut c_void, state.len()) {
+        return Err("the core didn't save its state".to_string());
+    }
+
+    // the same frames must come again after loading the state
+    let mut hashes = Vec::new();
+    for _ in 0..2 {
+        OUTPUT.lock().unwrap().reset();
+        for _ in 0..FRAMES {
+            retro_run();
+        }
+        let output = OUTPUT.lock().unwrap();
+        let expected_audio = (av_info.sample_rate / av_info.fps) as usize * FRAMES as usize;
+        if output.frames != FRAMES || output.audio_frames != expected_audio {
+            return Err(format!(
+                "{} frames and {} audio frames for {} frames run, instead of {}",
+                output.frames, output.audio_frames, FRAMES, expected_audio
+            ));
+        }
+        hashes.push(output.hash);
+        drop(output);
+
+        if !retro_unserialize(state.as_ptr() as *const c_void, state.len()) {
+            return Err("the core didn't load its state".to_string());
+        }
+    }
+    if hashes[0] != hashes[1] {
+        return Err(format!(
+            "the frames differ after loading the state: {:016x} and {:016x}",
+            hashes[0], hashes[1]
+        ));
+    }
+    println!(
+        "{} frames with sound, the same again from the save state of {} bytes ({:016x})",
+        FRAMES * 3,
+        state.len(),
+        hashes[0]
+    );
+
+    retro_unload_game();
+    retro_deinit();
+    Ok(())
+}
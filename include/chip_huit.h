@@ -0,0 +1,162 @@
+#ifndef CHIP_HUIT_H
+#define CHIP_HUIT_H
+
+/* Generated from src/ffi.rs by `cbindgen --config cbindgen.toml --output include/chip_huit.h`, don't edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// An emulator with a program, driven by the host which calls `chip8_step_frame`
+// 60 times per second.
+typedef struct Chip8Emulator Chip8Emulator;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// A new emulator without program, to free with `chip8_free`.
+struct Chip8Emulator *chip8_new(void);
+
+// Nothing happens when `emulator` is null.
+//
+// # Safety
+//
+// `emulator` must be null or come from `chip8_new`, and isn't valid anymore.
+void chip8_free(struct Chip8Emulator *emulator);
+
+// The last error, valid until the next one or until the emulator is freed.
+//
+// # Safety
+//
+// `emulator` must be null or come from `chip8_new`.
+const char *chip8_last_error(const struct Chip8Emulator *emulator);
+
+// Starts a program from scratch, with its settings from the ROM database
+// or on the platform guessed from its instructions.
+//
+// # Safety
+//
+// `emulator` must be null or come from `chip8_new`, and `rom` must point to `size`
+// readable bytes.
+int chip8_load_rom(struct Chip8Emulator *emulator, const uint8_t *rom, size_t size);
+
+// # Safety
+//
+// `emulator` must be null or come from `chip8_new`.
+int chip8_step_frame(struct Chip8Emulator *emulator);
+
+// 64 or 128 pixels, it changes with the resolution of the program.
+//
+// # Safety
+//
+// `emulator` must be null or come from `chip8_new`.
+size_t chip8_width(const struct Chip8Emulator *emulator);
+
+// 32 or 64 pixels.
+//
+// # Safety
+//
+// `emulator` must be null or come from `chip8_new`.
+size_t chip8_height(const struct Chip8Emulator *emulator);
+
+// The display in RGBA, `chip8_width` × `chip8_height` pixels row by row,
+// valid until the next call on the emulator.
+//
+// # Safety
+//
+// `emulator` must be null or come from `chip8_new`.
+const uint8_t *chip8_framebuffer(struct Chip8Emulator *emulator);
+
+// Presses or releases the keypad key 0 to F, the others are errors.
+//
+// # Safety
+//
+// `emulator` must be null or come from `chip8_new`.
+int chip8_set_key(struct Chip8Emulator *emulator, uint8_t key, bool is_pressed);
+
+// # Safety
+//
+// `emulator` must be null or come from `chip8_new`.
+bool chip8_is_sound_playing(const struct Chip8Emulator *emulator);
+
+// Copies the 16 bytes of the sound into `pattern`,
+// played at 4000 * 2^((pitch - 64) / 48) bits per second.
+//
+// # Safety
+//
+// `emulator` must be null or come from `chip8_new`, and `pattern` must be null or
+// point to 16 writable bytes.
+int chip8_audio_pattern(const struct Chip8Emulator *emulator, uint8_t *pattern);
+
+// # Safety
+//
+// `emulator` must be null or come from `chip8_new`.
+uint8_t chip8_pitch(const struct Chip8Emulator *emulator);
+
+// A palette name or colours like `000000,FFFFFF`, as on the command line.
+//
+// # Safety
+//
+// `emulator` must be null or come from `chip8_new`, and `palette` must be null or
+// a C string.
+int chip8_set_palette(struct Chip8Emulator *emulator, const char *palette);
+
+// Instructions executed per frame.
+//
+// # Safety
+//
+// `emulator` must be null or come from `chip8_new`.
+int chip8_set_speed(struct Chip8Emulator *emulator, uint32_t cycles_per_frame);
+
+// Makes `CXNN` draw the same numbers at every run.
+//
+// # Safety
+//
+// `emulator` must be null or come from `chip8_new`.
+int chip8_set_seed(struct Chip8Emulator *emulator, uint64_t seed);
+
+// The memory of the machine, `chip8_memory_size` bytes.
+//
+// # Safety
+//
+// `emulator` must be null or come from `chip8_new`.
+uint8_t *chip8_memory(struct Chip8Emulator *emulator);
+
+// # Safety
+//
+// `emulator` must be null or come from `chip8_new`.
+size_t chip8_memory_size(const struct Chip8Emulator *emulator);
+
+// A size large enough for the save states of the current platform.
+//
+// # Safety
+//
+// `emulator` must be null or come from `chip8_new`.
+size_t chip8_state_size(const struct Chip8Emulator *emulator);
+
+// Writes the state of the machine into `state`, and gives its size,
+// or 0 when `size` is too small.
+//
+// # Safety
+//
+// `emulator` must be null or come from `chip8_new`, and `state` must point to `size`
+// writable bytes.
+size_t chip8_save_state(const struct Chip8Emulator *emulator, uint8_t *state, size_t size);
+
+// Puts the machine back in a state written by `chip8_save_state`,
+// nothing changes when it is invalid.
+//
+// # Safety
+//
+// `emulator` must be null or come from `chip8_new`, and `state` must point to `size`
+// readable bytes.
+int chip8_load_state(struct Chip8Emulator *emulator, const uint8_t *state, size_t size);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* CHIP_HUIT_H */
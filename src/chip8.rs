@@ -1,55 +1,242 @@
-mod graphic_engine;
+pub mod assembler;
+pub mod audio;
+pub mod batch;
+pub mod database;
+pub mod disassembler;
+pub mod display;
+pub mod environment;
+pub mod frontends;
+pub mod gif_recorder;
+pub mod graphic_engine;
+pub mod headless_interface;
 mod opcode;
-pub mod sdl_interface;
+pub mod persistence;
+#[cfg(feature = "piston")]
 pub mod piston_interface;
+pub mod quirks;
+pub mod screenshot;
+#[cfg(feature = "sdl")]
+pub mod sdl_interface;
+#[cfg(feature = "software")]
+pub mod software_interface;
+mod state;
+#[cfg(feature = "terminal")]
+pub mod terminal_interface;
+pub mod trace_diff;
+pub mod tracer;
+pub mod video_recorder;
+pub mod viewport;
 
-use graphic_engine::GraphicEngine;
+use audio::AudioRecorder;
+use database::RomInfo;
+use disassembler::Disassembly;
+use display::Display;
+use graphic_engine::{EngineSettings, GraphicEngine};
+use headless_interface::HeadlessInterface;
 use opcode::OpCode;
-use rand::prelude::*;
-use sdl_interface::SdlInterface;
-use piston_interface::PistonInterface;
-use std::time::Duration;
+use quirks::{Platform, Quirks};
+use rand::{rngs::OsRng, Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use tracer::{Category, Level, Tracer};
 
-const RAM_SIZE: usize = 4096;
 const REGISTER_SIZE: usize = 16;
 const STACK_SIZE: usize = 16;
 const OFFSET_USABLE_MEM: usize = 0x200;
 pub const SCREEN_WIDTH: u32 = 64;
 pub const SCREEN_HEIGHT: u32 = 32;
-const FREQUENCY: u32 = 60;
+/// Frames per second.
+pub const FREQUENCY: u32 = 60;
+const DEFAULT_CYCLES_PER_FRAME: u32 = 10;
+const SMALL_FONT_ADDRESS: usize = 0x50;
+const BIG_FONT_ADDRESS: usize = 0xA0;
+
+/// 4x5 hexadecimal digits.
+const SMALL_FONT: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
 
-pub struct Chip8 {
-    ram: [u8; RAM_SIZE],
+/// 8x10 hexadecimal digits, SuperChip only has 0 to 9.
+const BIG_FONT: [u8; 160] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+/// The machine, drawing through any engine by default, or through a known one
+/// like `HeadlessInterface` to be `Send`, to run on other threads.
+pub struct Chip8<E: ?Sized = dyn GraphicEngine> {
+    ram: Vec<u8>,
     v: [u8; REGISTER_SIZE], // registers
     i: usize,               // address register
     stack: Vec<usize>,
     delay_timer: u8,
     sound_timer: u8,
     pc: usize, // program counter
-    old_pc: usize,
     is_pc_blocked: bool,
-    g_engine: Box<dyn GraphicEngine>,
+    g_engine: Box<E>,
     is_on: bool,
+    halt_reason: Option<String>, // why the CPU stopped on an error
+    tracer: Tracer,
+    cycle: u64, // executed instructions
+    display: Display,
+    planes: u8, // planes drawn and cleared (XO-CHIP)
+    keypad: [bool; 16],
+    frame_keypad: [bool; 16],   // during the previous frame
+    released_key: Option<u8>,   // released during the current frame
+    flags: [u8; REGISTER_SIZE], // persistent flags (SuperChip)
+    audio_pattern: [u8; 16],
+    pitch: u8,
+    audio: Option<AudioRecorder>,
+    audio_error: Option<String>, // the recording stopped on it
+    platform: Platform,
+    quirks: Quirks,
+    cycles_per_frame: u32,
+    has_drawn: bool, // during the current frame
+    frame_start_cycle: u64,
+    seed: u64,
+    rng: ChaCha20Rng, // seeded with `seed`, its position is saved in the states
 }
 
-impl Chip8 {
-    pub fn new() -> Chip8 {
-        Chip8 {
-            ram: [0; RAM_SIZE],
+impl<E: GraphicEngine + ?Sized> Chip8<E> {
+    pub fn new(g_engine: Box<E>) -> Chip8<E> {
+        let mut chip = Chip8 {
+            ram: Vec::new(),
             v: [0; REGISTER_SIZE],
             i: 0,
-            stack: vec![0; STACK_SIZE],
+            stack: Vec::with_capacity(STACK_SIZE),
             delay_timer: 0,
             sound_timer: 0,
             pc: OFFSET_USABLE_MEM,
-            old_pc: 0,
             is_pc_blocked: false,
-            // g_engine: Box::new(SdlInterface::new()),
-            g_engine: Box::new(PistonInterface::new()),
+            g_engine,
             is_on: true,
+            halt_reason: None,
+            tracer: Tracer::new(),
+            cycle: 0,
+            display: Display::new(),
+            planes: 1,
+            keypad: [false; 16],
+            frame_keypad: [false; 16],
+            released_key: None,
+            flags: [0; REGISTER_SIZE],
+            audio_pattern: audio::DEFAULT_PATTERN,
+            pitch: audio::DEFAULT_PITCH,
+            audio: None,
+            audio_error: None,
+            platform: Platform::Chip8,
+            quirks: Platform::Chip8.quirks(),
+            cycles_per_frame: DEFAULT_CYCLES_PER_FRAME,
+            has_drawn: false,
+            frame_start_cycle: 0,
+            seed: 0,
+            rng: ChaCha20Rng::seed_from_u64(0),
+        };
+        chip.set_platform(Platform::Chip8);
+        chip.set_seed(OsRng.next_u64());
+        chip
+    }
+
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = tracer;
+    }
+
+    /// Resets the memory to the size of the platform and selects its quirks,
+    /// so it has to be called before `load` and `set_quirks`.
+    pub fn set_platform(&mut self, platform: Platform) {
+        self.platform = platform;
+        self.quirks = platform.quirks();
+        self.ram = vec![0; platform.memory_size()];
+        self.ram[SMALL_FONT_ADDRESS..SMALL_FONT_ADDRESS + SMALL_FONT.len()]
+            .copy_from_slice(&SMALL_FONT);
+        self.ram[BIG_FONT_ADDRESS..BIG_FONT_ADDRESS + BIG_FONT.len()].copy_from_slice(&BIG_FONT);
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    /// Number of instructions executed every frame (60 frames per second).
+    pub fn set_speed(&mut self, cycles_per_frame: u32) {
+        self.cycles_per_frame = cycles_per_frame;
+    }
+
+    /// Makes `CXNN` draw the same numbers at every run.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = ChaCha20Rng::seed_from_u64(seed);
+        // the position of a generator which hasn't drawn anything can't be read
+        self.rng.set_word_pos(0);
+    }
+
+    /// Records the sound into a WAV file, following the emulated time.
+    pub fn set_audio_recorder(&mut self, recorder: AudioRecorder) {
+        self.audio = Some(recorder);
+    }
+
+    pub fn audio_recorder_mut(&mut self) -> Option<&mut AudioRecorder> {
+        self.audio.as_mut()
+    }
+
+    /// The audio recorder, to finish the file once the emulation is over.
+    pub fn take_audio_recorder(&mut self) -> Option<AudioRecorder> {
+        self.audio.take()
+    }
+
+    /// The error which stopped the audio recording, if any.
+    pub fn audio_error(&self) -> Option<&str> {
+        self.audio_error.as_deref()
+    }
+
+    /// Passes a change of the sound to the audio recorder, with the sample of the frame
+    /// matching the current instruction. The recording stops on errors.
+    fn record_audio<F>(&mut self, change: F)
+    where
+        F: FnOnce(&mut AudioRecorder, usize) -> Result<(), String>,
+    {
+        let instruction = (self.cycle - self.frame_start_cycle) as usize;
+        let sample = instruction * audio::SAMPLES_PER_FRAME / self.cycles_per_frame.max(1) as usize;
+
+        if let Some(ref mut recorder) = self.audio {
+            if let Err(error) = change(recorder, sample) {
+                self.tracer.log(Level::Info, Category::Timers, self.pc, || {
+                    format!("Audio recording stopped: {}", error)
+                });
+                self.audio_error = Some(error);
+                self.audio = None;
+            }
         }
     }
 
+    /// Called at the end of every frame, the sound stops with the frame
+    /// where the sound timer reaches 0.
     fn timer_countdown(&mut self) {
         if self.delay_timer > 0 {
             self.delay_timer -= 1;
@@ -57,26 +244,40 @@ impl Chip8 {
         if self.sound_timer > 0 {
             self.sound_timer -= 1;
         }
+
+        let is_playing = self.sound_timer > 0;
+        self.record_audio(|recorder, _| recorder.end_frame(is_playing));
     }
 
-    fn get_opcode(&self) -> u16 {
-        ((self.ram[self.pc] as u16) << 8) + self.ram[self.pc + 1] as u16
+    /// Addresses wrap around the memory.
+    fn read_byte(&self, address: usize) -> u8 {
+        self.ram[address % self.ram.len()]
     }
 
-    fn skip_next_instruction(&mut self) {
-        self.pc += 2;
+    fn write_byte(&mut self, address: usize, value: u8) {
+        let size = self.ram.len();
+        self.ram[address % size] = value;
     }
 
-    fn is_key_pressed(key: u8) -> bool {
-        todo!()
+    fn read_word(&self, address: usize) -> u16 {
+        ((self.read_byte(address) as u16) << 8) + self.read_byte(address + 1) as u16
+    }
+
+    fn get_opcode(&self) -> u16 {
+        self.read_word(self.pc)
     }
 
-    fn get_pressed_key() -> u8 {
-        todo!()
+    fn skip_next_instruction(&mut self) {
+        // F000 NNNN is the only instruction taking 4 bytes
+        if self.platform == Platform::XoChip && self.read_word(self.pc + 2) == 0xF000 {
+            self.pc += 4;
+        } else {
+            self.pc += 2;
+        }
     }
 
-    fn get_sprite_address(character: u8) -> usize {
-        todo!()
+    fn is_key_pressed(&self, key: u8) -> bool {
+        self.keypad[(key & 0xF) as usize]
     }
 
     fn next_operation(&mut self) {
@@ -91,81 +292,257 @@ impl Chip8 {
         self.is_pc_blocked = true;
     }
 
+    /// Stops the CPU, the window stays open.
+    fn halt(&mut self, reason: &str) {
+        let reason = format!("{} (PC: 0x{:03X})", reason, self.pc);
+        self.tracer.log(Level::Info, Category::Cpu, self.pc, || {
+            format!("{}, stopping execution!", reason)
+        });
+        self.halt_reason = Some(reason);
+        self.is_on = false;
+    }
+
+    /// Instructions of later platforms are unknown to the earlier ones.
+    fn is_supported(&mut self, platform: Platform) -> bool {
+        if self.platform < platform {
+            let opcode = self.get_opcode();
+            self.unknown(opcode);
+            return false;
+        }
+        true
+    }
+
     fn execute_current_operation(&mut self) {
         let opcode = self.get_opcode();
-        println!("${:X?}: {:04X?}", self.pc, opcode);
+        self.tracer
+            .instruction(self.cycle, self.pc, opcode, &self.v, self.i);
         self.execute_opcode(opcode);
+        self.cycle += 1;
     }
 
-    pub fn run(&mut self) {
-        self.g_engine.init_draw();
-
-        let mut frequency = FREQUENCY;
+    /// Reads the keypad, executes the instructions of one frame,
+    /// counts the timers down and shows the display if it changed.
+    pub fn step_frame(&mut self) {
+        self.run_frame(u64::MAX);
+    }
 
-        while self.g_engine.is_running() {
-            if self.is_on {
-                self.old_pc = self.pc;
-                self.execute_current_operation();
-                self.next_operation();
+    /// Runs frames until `cycle` instructions are executed, stopping in the middle of a frame,
+    /// so the machine is as a trace line shows it before the instruction `cycle`.
+    pub fn run_to_cycle(&mut self, cycle: u64) {
+        while self.cycle < cycle && self.is_on && self.cycles_per_frame > 0 {
+            self.run_frame(cycle);
+        }
+    }
 
-                // infinite loop detection
-                if self.old_pc == self.pc {
-                    println!("Infinite loop detected, stopping execution!");
-                    self.is_on = false;
-                }
+    /// A frame, left unfinished when the instruction `end_cycle` is reached.
+    fn run_frame(&mut self, end_cycle: u64) {
+        self.g_engine.flush(&mut self.keypad);
+        self.released_key = (0..16)
+            .find(|&key| self.frame_keypad[key] && !self.keypad[key])
+            .map(|key| key as u8);
+        self.frame_keypad = self.keypad;
 
-                self.timer_countdown();
-            } else {
-                frequency = 5;
+        self.has_drawn = false;
+        self.frame_start_cycle = self.cycle;
+        for _ in 0..self.cycles_per_frame {
+            // sprites wait for the vertical blank interrupt
+            if !self.is_on || (self.quirks.display_wait && self.has_drawn) {
+                break;
+            }
+            if self.cycle == end_cycle {
+                return;
             }
+            self.execute_current_operation();
+            self.next_operation();
+        }
 
-            self.g_engine.flush();
+        self.timer_countdown();
 
-            std::thread::sleep(Duration::new(0, 1_000_000_000u32 / frequency));
+        if self.display.take_changes() {
+            self.g_engine.draw(&self.display);
         }
     }
 
-    pub fn read(&mut self, p: &std::path::Path) {
-        println!(
-            "Reading file '{}'...",
-            p.file_name().unwrap().to_str().unwrap()
-        );
+    /// Opens the engine, before the first frame.
+    pub fn start(&mut self) {
+        self.g_engine.init_draw();
+    }
 
-        let data = std::fs::read(p).unwrap();
+    /// The engine is still open, and when headless, the program still runs.
+    pub fn is_running(&self) -> bool {
+        self.g_engine.is_running() && (self.is_on || !self.g_engine.is_headless())
+    }
+
+    /// Ends the engine after the last frame, with the error of a file it couldn't write.
+    pub fn finish(&mut self) -> Result<(), String> {
+        self.g_engine.finish()
+    }
 
-        let mut i = OFFSET_USABLE_MEM;
+    /// Why the CPU stopped on an error, like `Infinite loop detected (PC: 0x218)`.
+    pub fn halt_reason(&self) -> Option<&str> {
+        self.halt_reason.as_deref()
+    }
+
+    /// Runs as fast as possible rather than at `FREQUENCY` frames per second.
+    pub fn is_headless(&self) -> bool {
+        self.g_engine.is_headless()
+    }
+
+    /// Presses or releases a key of the keypad, for callers driving the frames.
+    pub fn set_key(&mut self, key: u8, is_pressed: bool) {
+        self.keypad[(key & 0xF) as usize] = is_pressed;
+    }
+
+    /// The whole memory, for debuggers, cheats and the programs watching a game.
+    pub fn memory(&self) -> &[u8] {
+        &self.ram
+    }
+
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    /// The registers V0 to VF.
+    pub fn registers(&self) -> &[u8; REGISTER_SIZE] {
+        &self.v
+    }
+
+    pub fn registers_mut(&mut self) -> &mut [u8; REGISTER_SIZE] {
+        &mut self.v
+    }
+
+    /// The address register I.
+    pub fn index(&self) -> usize {
+        self.i
+    }
+
+    pub fn set_index(&mut self, address: usize) {
+        self.i = address;
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn set_pc(&mut self, address: usize) {
+        self.pc = address;
+    }
+
+    /// The delay and sound timers.
+    pub fn timers(&self) -> (u8, u8) {
+        (self.delay_timer, self.sound_timer)
+    }
+
+    pub fn set_timers(&mut self, delay: u8, sound: u8) {
+        self.delay_timer = delay;
+        self.sound_timer = sound;
+    }
+
+    pub fn display(&self) -> &Display {
+        &self.display
+    }
+
+    /// The buzzer sounds while the sound timer isn't 0.
+    pub fn is_sound_playing(&self) -> bool {
+        self.sound_timer > 0
+    }
+
+    /// The 1-bit pattern of 128 samples played by the buzzer (XO-CHIP).
+    pub fn audio_pattern(&self) -> [u8; 16] {
+        self.audio_pattern
+    }
+
+    /// The playback rate of the pattern: 4000 * 2^((pitch - 64) / 48) samples per second.
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
 
-        for byte in data {
-            self.ram[i] = byte;
-            i += 1;
+    /// Copies the program at 0x200.
+    pub fn load(&mut self, rom: &[u8]) -> Result<(), String> {
+        let max_size = self.ram.len() - OFFSET_USABLE_MEM;
+        if rom.len() > max_size {
+            return Err(format!(
+                "the program is {} bytes long but only {} bytes fit in {} memory",
+                rom.len(),
+                max_size,
+                self.platform
+            ));
         }
 
-        println!("Done! {} bytes read.", i - OFFSET_USABLE_MEM);
+        self.ram[OFFSET_USABLE_MEM..OFFSET_USABLE_MEM + rom.len()].copy_from_slice(rom);
+        Ok(())
     }
 }
 
-impl OpCode for Chip8 {
+/// The emulators driven by their callers, for the bindings and the batches.
+impl Chip8<HeadlessInterface> {
+    /// An emulator without window, driven frame by frame by its caller.
+    pub fn headless() -> Chip8<HeadlessInterface> {
+        Chip8::new(Box::new(HeadlessInterface::new(
+            None,
+            EngineSettings::default(),
+        )))
+    }
+
+    /// A headless emulator with `rom` loaded, set up like the ROM database says
+    /// or on the platform guessed from its instructions.
+    /// Also gives what the database knows, like the palette and the keymap.
+    pub fn for_rom(rom: &[u8]) -> Result<(Chip8<HeadlessInterface>, Option<RomInfo>), String> {
+        let known = database::lookup(rom);
+        let mut chip = Chip8::headless();
+
+        chip.set_platform(
+            known
+                .as_ref()
+                .and_then(|info| info.platform)
+                .unwrap_or_else(|| Disassembly::new(rom).platform()),
+        );
+        if let Some(ref info) = known {
+            if let Some(quirks) = info.quirks {
+                chip.set_quirks(quirks);
+            }
+            if let Some(speed) = info.speed {
+                chip.set_speed(speed);
+            }
+        }
+        chip.load(rom)?;
+
+        Ok((chip, known))
+    }
+}
+
+impl<E: GraphicEngine + ?Sized> OpCode for Chip8<E> {
     fn op1(&mut self) {
-        println!("Opcode 0NNN, shutting down...");
-        self.is_on = false;
+        self.halt("Opcode 0NNN, shutting down");
     }
     fn op2(&mut self) {
-        self.g_engine.clear_screen();
+        self.tracer.log(Level::Info, Category::Draw, self.pc, || {
+            "Clear screen".to_string()
+        });
+        self.display.clear(self.planes);
     }
     fn op3(&mut self) {
-        let option = self.stack.pop();
-        self.pc = match option {
-            Some(n) => n,
-            None => panic!("Returning from subroutine failed: the stack is empty!"),
-        };
+        match self.stack.pop() {
+            Some(n) => self.pc = n,
+            None => self.halt("Returning from subroutine failed: the stack is empty"),
+        }
     }
     fn op4(&mut self, nnn: usize) {
+        if nnn == self.pc {
+            self.halt("Infinite loop detected");
+            return;
+        }
         self.pc = nnn;
         self.block_pc();
     }
     fn op5(&mut self, nnn: usize) {
+        if self.stack.len() == STACK_SIZE {
+            self.halt("Calling subroutine failed: the stack is full");
+            return;
+        }
         self.stack.push(self.pc);
-        self.op4(nnn);
+        self.pc = nnn;
+        self.block_pc();
     }
     fn op6(&mut self, x: usize, nn: u8) {
         if self.v[x] == nn {
@@ -186,55 +563,62 @@ impl OpCode for Chip8 {
         self.v[x] = nn;
     }
     fn op10(&mut self, x: usize, nn: u8) {
-        // I use this strange operation to simulate a overflow
-        self.v[x] = ((self.v[x] as u16 + nn as u16) % 0xFF) as u8;
+        self.v[x] = self.v[x].wrapping_add(nn);
     }
     fn op11(&mut self, x: usize, y: usize) {
         self.v[x] = self.v[y];
     }
     fn op12(&mut self, x: usize, y: usize) {
         self.v[x] |= self.v[y];
+        if self.quirks.vf_reset {
+            self.v[0xF] = 0;
+        }
     }
     fn op13(&mut self, x: usize, y: usize) {
         self.v[x] &= self.v[y];
+        if self.quirks.vf_reset {
+            self.v[0xF] = 0;
+        }
     }
     fn op14(&mut self, x: usize, y: usize) {
         self.v[x] ^= self.v[y];
-    }
-    fn op15(&mut self, x: usize, y: usize) {
-        if (self.v[x] as u16 + self.v[y] as u16) > 0xFF {
-            self.v[0xF] = 1;
-        } else {
+        if self.quirks.vf_reset {
             self.v[0xF] = 0;
         }
-
-        self.v[x] = ((self.v[x] as u16 + self.v[y] as u16) % 0xFF) as u8;
+    }
+    // the flag is written after the result, so VF can be an operand
+    fn op15(&mut self, x: usize, y: usize) {
+        let (result, carry) = self.v[x].overflowing_add(self.v[y]);
+        self.v[x] = result;
+        self.v[0xF] = carry as u8;
     }
     fn op16(&mut self, x: usize, y: usize) {
-        if self.v[x] < self.v[y] {
-            self.v[0xF] = 0;
-            self.v[x] += 0xFF - self.v[y]; // overflow simulation
-        } else {
-            self.v[0xF] = 1;
-            self.v[x] -= self.v[y];
-        }
+        let (result, borrow) = self.v[x].overflowing_sub(self.v[y]);
+        self.v[x] = result;
+        self.v[0xF] = !borrow as u8;
     }
-    fn op17(&mut self, x: usize) {
-        self.v[0xF] = self.v[x] & 0b1;
-        self.v[x] >>= 1;
+    fn op17(&mut self, x: usize, y: usize) {
+        let value = if self.quirks.shifting {
+            self.v[x]
+        } else {
+            self.v[y]
+        };
+        self.v[x] = value >> 1;
+        self.v[0xF] = value & 0b1;
     }
     fn op18(&mut self, x: usize, y: usize) {
-        if self.v[x] > self.v[y] {
-            self.v[0xF] = 0;
-        } else {
-            self.v[0xF] = 1;
-        }
-
-        self.v[x] = self.v[y] - self.v[x];
+        let (result, borrow) = self.v[y].overflowing_sub(self.v[x]);
+        self.v[x] = result;
+        self.v[0xF] = !borrow as u8;
     }
-    fn op19(&mut self, x: usize) {
-        self.v[0xF] = self.v[x] & 0b1000_0000;
-        self.v[x] <<= 1;
+    fn op19(&mut self, x: usize, y: usize) {
+        let value = if self.quirks.shifting {
+            self.v[x]
+        } else {
+            self.v[y]
+        };
+        self.v[x] = value << 1;
+        self.v[0xF] = value >> 7;
     }
     fn op20(&mut self, x: usize, y: usize) {
         if self.v[x] != self.v[y] {
@@ -245,26 +629,61 @@ impl OpCode for Chip8 {
         self.i = nnn;
     }
     fn op22(&mut self, nnn: usize) {
-        self.pc = nnn + self.v[0] as usize;
+        let offset = if self.quirks.jumping {
+            self.v[nnn >> 8]
+        } else {
+            self.v[0]
+        };
+        self.pc = nnn + offset as usize;
         self.block_pc();
     }
     fn op23(&mut self, x: usize, nn: u8) {
-        self.v[x] = nn & thread_rng().gen_range(0, 255);
+        self.v[x] = nn & self.rng.gen::<u8>();
     }
     fn op24(&mut self, x: usize, y: usize, n: u8) {
-        self.g_engine.draw_sprite(
-            self.v[x],
-            self.v[y],
-            &self.ram[self.i..self.i + 1 + n as usize],
+        // DXY0 draws a 16x16 sprite since SuperChip
+        let (rows, wide) = match n {
+            0 if self.platform >= Platform::SuperChip => (16, true),
+            _ => (n as usize, false),
+        };
+        let plane_count = self.planes.count_ones() as usize;
+        let size = rows * if wide { 2 } else { 1 } * plane_count;
+        let sprite: Vec<u8> = (0..size)
+            .map(|offset| self.read_byte(self.i + offset))
+            .collect();
+
+        let (vx, vy) = (self.v[x], self.v[y]);
+        self.tracer.log(Level::Debug, Category::Draw, self.pc, || {
+            format!("({} ; {}) {:?}", vx, vy, sprite)
+        });
+
+        let collision = self.display.draw_sprite(
+            vx as usize,
+            vy as usize,
+            rows,
+            wide,
+            &sprite,
+            self.planes,
+            self.quirks.clipping,
         );
+        self.v[0xF] = collision as u8;
+        self.has_drawn = true;
     }
     fn op25(&mut self, x: usize) {
-        if Chip8::is_key_pressed(self.v[x]) {
+        let key = self.v[x];
+        self.tracer.log(Level::Debug, Category::Input, self.pc, || {
+            format!("Skip if key {:X} is pressed", key)
+        });
+        if self.is_key_pressed(key) {
             self.skip_next_instruction();
         }
     }
     fn op26(&mut self, x: usize) {
-        if !Chip8::is_key_pressed(self.v[x]) {
+        let key = self.v[x];
+        self.tracer.log(Level::Debug, Category::Input, self.pc, || {
+            format!("Skip if key {:X} isn't pressed", key)
+        });
+        if !self.is_key_pressed(key) {
             self.skip_next_instruction();
         }
     }
@@ -272,33 +691,190 @@ impl OpCode for Chip8 {
         self.v[x] = self.delay_timer;
     }
     fn op28(&mut self, x: usize) {
-        self.v[x] = Chip8::get_pressed_key();
+        // like the COSMAC VIP, the key is taken when it is released
+        match self.released_key.take() {
+            Some(key) => {
+                self.tracer.log(Level::Info, Category::Input, self.pc, || {
+                    format!("Key {:X} released", key)
+                });
+                self.v[x] = key;
+            }
+            None => self.block_pc(),
+        }
     }
     fn op29(&mut self, x: usize) {
+        let value = self.v[x];
+        self.tracer
+            .log(Level::Debug, Category::Timers, self.pc, || {
+                format!("Delay timer set to {}", value)
+            });
         self.delay_timer = self.v[x];
     }
     fn op30(&mut self, x: usize) {
+        let value = self.v[x];
+        self.tracer.log(Level::Info, Category::Timers, self.pc, || {
+            format!("Sound timer set to {}", value)
+        });
         self.sound_timer = self.v[x];
+        self.record_audio(|recorder, sample| recorder.set_playing(sample, value > 0));
     }
     fn op31(&mut self, x: usize) {
-        self.i += self.v[x] as usize;
+        self.i = (self.i + self.v[x] as usize) & 0xFFFF;
     }
     fn op32(&mut self, x: usize) {
-        self.i = Chip8::get_sprite_address(self.v[x]);
+        self.i = SMALL_FONT_ADDRESS + (self.v[x] & 0xF) as usize * 5;
     }
     fn op33(&mut self, x: usize) {
-        self.ram[self.i] = self.v[x] / 100;
-        self.ram[self.i + 1] = (self.v[x] / 10) % 10;
-        self.ram[self.i + 2] = self.v[x] % 10;
+        let value = self.v[x];
+        self.write_byte(self.i, value / 100);
+        self.write_byte(self.i + 1, (value / 10) % 10);
+        self.write_byte(self.i + 2, value % 10);
     }
     fn op34(&mut self, x: usize) {
-        for offset in 0..x {
-            self.ram[self.i + offset] = self.v[offset];
+        for offset in 0..=x {
+            self.write_byte(self.i + offset, self.v[offset]);
+        }
+        if self.quirks.memory {
+            self.i += x + 1;
         }
     }
     fn op35(&mut self, x: usize) {
-        for offset in 0..x {
-            self.v[offset] = self.ram[self.i + offset];
+        for offset in 0..=x {
+            self.v[offset] = self.read_byte(self.i + offset);
+        }
+        if self.quirks.memory {
+            self.i += x + 1;
+        }
+    }
+    fn op36(&mut self, n: u8) {
+        if !self.is_supported(Platform::SuperChip) {
+            return;
+        }
+        self.display.scroll_down(n as usize, self.planes);
+    }
+    fn op37(&mut self, n: u8) {
+        if !self.is_supported(Platform::XoChip) {
+            return;
+        }
+        self.display.scroll_up(n as usize, self.planes);
+    }
+    fn op38(&mut self) {
+        if !self.is_supported(Platform::SuperChip) {
+            return;
+        }
+        self.display.scroll_right(4, self.planes);
+    }
+    fn op39(&mut self) {
+        if !self.is_supported(Platform::SuperChip) {
+            return;
+        }
+        self.display.scroll_left(4, self.planes);
+    }
+    fn op40(&mut self) {
+        if !self.is_supported(Platform::SuperChip) {
+            return;
+        }
+        self.tracer
+            .log(Level::Info, Category::Cpu, self.pc, || "Exit".to_string());
+        self.is_on = false;
+        self.block_pc();
+    }
+    fn op41(&mut self) {
+        if !self.is_supported(Platform::SuperChip) {
+            return;
+        }
+        self.display.set_hires(false);
+    }
+    fn op42(&mut self) {
+        if !self.is_supported(Platform::SuperChip) {
+            return;
+        }
+        self.display.set_hires(true);
+    }
+    fn op43(&mut self, x: usize, y: usize) {
+        if !self.is_supported(Platform::XoChip) {
+            return;
+        }
+        let registers: Vec<usize> = if x <= y {
+            (x..=y).collect()
+        } else {
+            (y..=x).rev().collect()
+        };
+        for (offset, register) in registers.into_iter().enumerate() {
+            self.write_byte(self.i + offset, self.v[register]);
+        }
+    }
+    fn op44(&mut self, x: usize, y: usize) {
+        if !self.is_supported(Platform::XoChip) {
+            return;
+        }
+        let registers: Vec<usize> = if x <= y {
+            (x..=y).collect()
+        } else {
+            (y..=x).rev().collect()
+        };
+        for (offset, register) in registers.into_iter().enumerate() {
+            self.v[register] = self.read_byte(self.i + offset);
+        }
+    }
+    fn op45(&mut self) {
+        if !self.is_supported(Platform::XoChip) {
+            return;
+        }
+        self.i = self.read_word(self.pc + 2) as usize;
+        self.pc += 2;
+    }
+    fn op46(&mut self, n: u8) {
+        if !self.is_supported(Platform::XoChip) {
+            return;
+        }
+        self.planes = n & 0b11;
+    }
+    fn op47(&mut self) {
+        if !self.is_supported(Platform::XoChip) {
+            return;
         }
+        for offset in 0..self.audio_pattern.len() {
+            self.audio_pattern[offset] = self.read_byte(self.i + offset);
+        }
+        let pattern = self.audio_pattern;
+        self.tracer
+            .log(Level::Debug, Category::Timers, self.pc, || {
+                format!("Audio pattern set to {:02X?}", pattern)
+            });
+        self.record_audio(|recorder, sample| recorder.set_pattern(sample, pattern));
+    }
+    fn op48(&mut self, x: usize) {
+        if !self.is_supported(Platform::SuperChip) {
+            return;
+        }
+        self.i = BIG_FONT_ADDRESS + (self.v[x] & 0xF) as usize * 10;
+    }
+    fn op49(&mut self, x: usize) {
+        if !self.is_supported(Platform::XoChip) {
+            return;
+        }
+        self.pitch = self.v[x];
+        let pitch = self.pitch;
+        self.tracer
+            .log(Level::Debug, Category::Timers, self.pc, || {
+                format!("Pitch set to {}", pitch)
+            });
+        self.record_audio(|recorder, sample| recorder.set_pitch(sample, pitch));
+    }
+    fn op50(&mut self, x: usize) {
+        if !self.is_supported(Platform::SuperChip) {
+            return;
+        }
+        self.flags[..=x].copy_from_slice(&self.v[..=x]);
+    }
+    fn op51(&mut self, x: usize) {
+        if !self.is_supported(Platform::SuperChip) {
+            return;
+        }
+        self.v[..=x].copy_from_slice(&self.flags[..=x]);
+    }
+    fn unknown(&mut self, opcode: u16) {
+        self.halt(&format!("Unknown opcode provided! {:04X}", opcode));
     }
 }
@@ -0,0 +1,1039 @@
+mod expression;
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+
+use super::OFFSET_USABLE_MEM;
+
+/// XO-CHIP programs can address the whole 64K of memory with `i := long`.
+const MEMORY_SIZE: usize = 0x10000;
+
+#[derive(Clone)]
+struct Token {
+    text: String,
+    line: usize,
+}
+
+#[derive(Debug)]
+pub struct AssemblyError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssemblyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+type Result<T> = std::result::Result<T, AssemblyError>;
+
+/// An assembled ROM, ready to be loaded at `OFFSET_USABLE_MEM`.
+pub struct Program {
+    pub rom: Vec<u8>,
+    pub labels: BTreeMap<String, usize>,
+}
+
+impl Program {
+    /// One `address name` line per label, sorted by address.
+    pub fn symbol_map(&self) -> String {
+        let mut symbols: Vec<(&usize, &String)> = self
+            .labels
+            .iter()
+            .map(|(name, address)| (address, name))
+            .collect();
+        symbols.sort();
+
+        symbols
+            .into_iter()
+            .map(|(address, name)| format!("0x{:04X} {}\n", address, name))
+            .collect()
+    }
+}
+
+/// How a forward reference is written once the label is known.
+#[derive(Clone, Copy)]
+enum Patch {
+    /// The 12 bits address of a `_NNN` opcode.
+    Address,
+    /// The 16 bits word following `i := long`.
+    Long,
+    /// The `v0 := N_` / `v1 := __` pair of `:unpack N`.
+    Unpack(u8),
+    /// The `v0 := __` / `v1 := __` pair of `:unpack long`.
+    UnpackLong,
+}
+
+struct ForwardReference {
+    name: String,
+    address: usize,
+    patch: Patch,
+    line: usize,
+}
+
+struct Macro {
+    parameters: Vec<String>,
+    body: Vec<Token>,
+    calls: usize,
+}
+
+/// A condition of `if`/`while`, `register` being compared to `operand`.
+struct Condition {
+    register: usize,
+    operator: String,
+    operand: Operand,
+}
+
+enum Operand {
+    Register(usize),
+    Byte(u8),
+    None,
+}
+
+struct Assembler {
+    tokens: VecDeque<Token>,
+    line: usize,
+    rom: Vec<u8>,
+    end: usize,
+    here: usize,
+    has_main: bool,
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, usize>,
+    macros: HashMap<String, Macro>,
+    /// The macros being expanded, with the number of tokens left once they are.
+    expansions: Vec<(String, usize)>,
+    forward_references: Vec<ForwardReference>,
+    branches: Vec<usize>,
+    loops: Vec<(usize, Vec<usize>)>,
+}
+
+/// Assembles Octo source code.
+/// The SuperChip and XO-CHIP instructions are understood as well.
+pub fn assemble(source: &str) -> std::result::Result<Program, AssemblyError> {
+    let mut assembler = Assembler::new(tokenize(source)?);
+    assembler.run()?;
+    assembler.finish()
+}
+
+fn tokenize(source: &str) -> Result<VecDeque<Token>> {
+    let mut tokens = VecDeque::new();
+
+    for (index, line) in source.lines().enumerate() {
+        let mut chars = line.chars().peekable();
+
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() {
+                chars.next();
+            } else if c == '#' {
+                break;
+            } else if c == '"' {
+                chars.next();
+                let mut text = String::from("\"");
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => text.push(c),
+                        None => {
+                            return Err(AssemblyError {
+                                line: index + 1,
+                                message: "Missing closing quote".to_string(),
+                            })
+                        }
+                    }
+                }
+                tokens.push_back(Token {
+                    text,
+                    line: index + 1,
+                });
+            } else {
+                let mut text = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() {
+                        break;
+                    }
+                    text.push(c);
+                    chars.next();
+                }
+                tokens.push_back(Token {
+                    text,
+                    line: index + 1,
+                });
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn parse_number(text: &str) -> Option<f64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()? as f64
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()? as f64
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse::<f64>().ok()?
+    } else {
+        return None;
+    };
+
+    Some(if negative { -value } else { value })
+}
+
+fn parse_register(text: &str) -> Option<usize> {
+    let mut chars = text.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('v'), Some(digit), None) | (Some('V'), Some(digit), None) => {
+            digit.to_digit(16).map(|digit| digit as usize)
+        }
+        _ => None,
+    }
+}
+
+impl Assembler {
+    fn new(tokens: VecDeque<Token>) -> Assembler {
+        let mut rom = vec![0; MEMORY_SIZE];
+        rom[OFFSET_USABLE_MEM] = 0x10;
+
+        Assembler {
+            tokens,
+            line: 1,
+            rom,
+            end: OFFSET_USABLE_MEM + 2,
+            here: OFFSET_USABLE_MEM + 2,
+            has_main: true,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            expansions: Vec::new(),
+            // Like Octo, the program starts with a jump to `main`,
+            // removed if `main` is the very first thing defined.
+            forward_references: vec![ForwardReference {
+                name: "main".to_string(),
+                address: OFFSET_USABLE_MEM,
+                patch: Patch::Address,
+                line: 1,
+            }],
+            branches: Vec::new(),
+            loops: Vec::new(),
+        }
+    }
+
+    fn error<T>(&self, message: String) -> Result<T> {
+        Err(AssemblyError {
+            line: self.line,
+            message,
+        })
+    }
+
+    fn next(&mut self) -> Result<Token> {
+        match self.tokens.pop_front() {
+            Some(token) => {
+                self.line = token.line;
+                Ok(token)
+            }
+            None => self.error("Unexpected end of file".to_string()),
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.front().map(|token| token.text.as_str())
+    }
+
+    fn expect(&mut self, text: &str) -> Result<()> {
+        let token = self.next()?;
+        if token.text != text {
+            return self.error(format!("Expected '{}', found '{}'", text, token.text));
+        }
+        Ok(())
+    }
+
+    fn emit_byte(&mut self, byte: u8) -> Result<()> {
+        if self.here >= MEMORY_SIZE {
+            return self.error("The program doesn't fit in memory".to_string());
+        }
+        self.rom[self.here] = byte;
+        self.here += 1;
+        if self.here > self.end {
+            self.end = self.here;
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, opcode: u16) -> Result<()> {
+        self.emit_byte((opcode >> 8) as u8)?;
+        self.emit_byte(opcode as u8)
+    }
+
+    fn is_register(&self, text: &str) -> bool {
+        parse_register(text).is_some() || self.aliases.contains_key(text)
+    }
+
+    fn register(&mut self) -> Result<usize> {
+        let token = self.next()?;
+        match parse_register(&token.text).or_else(|| self.aliases.get(&token.text).copied()) {
+            Some(register) => Ok(register),
+            None => self.error(format!("Expected a register, found '{}'", token.text)),
+        }
+    }
+
+    /// A number, a constant, a defined label or a `{ }` expression.
+    fn value(&mut self) -> Result<f64> {
+        let token = self.next()?;
+        self.value_of(&token.text)
+    }
+
+    fn value_of(&mut self, text: &str) -> Result<f64> {
+        if text == "{" {
+            return self.expression();
+        }
+        if let Some(value) = parse_number(text) {
+            return Ok(value);
+        }
+        if let Some(value) = self.constants.get(text) {
+            return Ok(*value);
+        }
+        if let Some(address) = self.labels.get(text) {
+            return Ok(*address as f64);
+        }
+        self.error(format!("Undefined name '{}'", text))
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        let value = self.value()? as i64;
+        if !(-128..=255).contains(&value) {
+            return self.error(format!("Value {} doesn't fit in a byte", value));
+        }
+        Ok(value as u8)
+    }
+
+    fn nibble(&mut self) -> Result<u16> {
+        let value = self.value()? as i64;
+        if !(0..=15).contains(&value) {
+            return self.error(format!("Value {} doesn't fit in a nibble", value));
+        }
+        Ok(value as u16)
+    }
+
+    /// An address that may be a label defined later in the source,
+    /// in that case the bytes at `address` are patched at the end.
+    fn address(&mut self, address: usize, patch: Patch) -> Result<u16> {
+        let token = self.next()?;
+        let is_name = token.text != "{"
+            && parse_number(&token.text).is_none()
+            && !self.constants.contains_key(&token.text);
+
+        if is_name && !self.labels.contains_key(&token.text) {
+            self.forward_references.push(ForwardReference {
+                name: token.text,
+                address,
+                patch,
+                line: token.line,
+            });
+            return Ok(0);
+        }
+
+        let value = self.value_of(&token.text)? as i64;
+        let max = match patch {
+            Patch::Address | Patch::Unpack(_) => 0xFFF,
+            Patch::Long | Patch::UnpackLong => 0xFFFF,
+        };
+        if !(0..=max).contains(&value) {
+            return self.error(format!("Address 0x{:X} is out of range", value));
+        }
+        Ok(value as u16)
+    }
+
+    fn patch(&mut self, address: usize, patch: Patch, value: usize) {
+        match patch {
+            Patch::Address => {
+                self.rom[address] = (self.rom[address] & 0xF0) | ((value >> 8) & 0xF) as u8;
+                self.rom[address + 1] = value as u8;
+            }
+            Patch::Long => {
+                self.rom[address] = (value >> 8) as u8;
+                self.rom[address + 1] = value as u8;
+            }
+            Patch::Unpack(nibble) => {
+                self.rom[address + 1] = (nibble << 4) | ((value >> 8) & 0xF) as u8;
+                self.rom[address + 3] = value as u8;
+            }
+            Patch::UnpackLong => {
+                self.rom[address + 1] = (value >> 8) as u8;
+                self.rom[address + 3] = value as u8;
+            }
+        }
+    }
+
+    fn define_label(&mut self, name: String, address: usize) -> Result<()> {
+        if self.labels.contains_key(&name) || self.constants.contains_key(&name) {
+            return self.error(format!("The name '{}' is already defined", name));
+        }
+        if name == "main" && self.has_main && self.here == OFFSET_USABLE_MEM + 2 {
+            // nothing precedes main, the initial jump is useless
+            self.has_main = false;
+            self.forward_references.remove(0);
+            self.here = OFFSET_USABLE_MEM;
+            self.end = OFFSET_USABLE_MEM;
+            return self.define_label(name, OFFSET_USABLE_MEM);
+        }
+        self.labels.insert(name, address);
+        Ok(())
+    }
+
+    fn run(&mut self) -> Result<()> {
+        while !self.tokens.is_empty() {
+            let token = self.next()?;
+            self.statement(token)?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Program> {
+        if !self.branches.is_empty() {
+            return self.error("Missing 'end'".to_string());
+        }
+        if !self.loops.is_empty() {
+            return self.error("Missing 'again'".to_string());
+        }
+
+        for reference in std::mem::take(&mut self.forward_references) {
+            match self.labels.get(&reference.name) {
+                Some(&value) => self.patch(reference.address, reference.patch, value),
+                None => {
+                    self.line = reference.line;
+                    return if reference.name == "main" {
+                        self.error("This program is missing a 'main' label".to_string())
+                    } else {
+                        self.error(format!("Undefined name '{}'", reference.name))
+                    };
+                }
+            }
+        }
+
+        Ok(Program {
+            rom: self.rom[OFFSET_USABLE_MEM..self.end].to_vec(),
+            labels: self.labels.into_iter().collect(),
+        })
+    }
+
+    fn statement(&mut self, token: Token) -> Result<()> {
+        if self.is_register(&token.text) {
+            return self.register_statement(&token.text);
+        }
+
+        match token.text.as_str() {
+            ":" => {
+                let name = self.next()?.text;
+                let here = self.here;
+                self.define_label(name, here)?;
+            }
+            ":next" => {
+                let name = self.next()?.text;
+                let here = self.here;
+                self.define_label(name, here + 1)?;
+            }
+            ":alias" => {
+                let name = self.next()?.text;
+                let register = self.register()?;
+                self.aliases.insert(name, register);
+            }
+            ":const" => {
+                let name = self.next()?.text;
+                let value = self.value()?;
+                self.constants.insert(name, value);
+            }
+            ":calc" => {
+                let name = self.next()?.text;
+                self.expect("{")?;
+                let value = self.expression()?;
+                self.constants.insert(name, value);
+            }
+            ":byte" => {
+                let byte = self.byte()?;
+                self.emit_byte(byte)?;
+            }
+            ":org" => {
+                let address = self.value()? as i64;
+                if !(0..MEMORY_SIZE as i64).contains(&address) {
+                    return self.error(format!("Address 0x{:X} is out of range", address));
+                }
+                self.here = address as usize;
+            }
+            ":macro" => self.define_macro()?,
+            ":unpack" => {
+                let here = self.here;
+                let patch = if self.peek() == Some("long") {
+                    self.next()?;
+                    Patch::UnpackLong
+                } else {
+                    Patch::Unpack(self.nibble()? as u8)
+                };
+                let address = self.address(here, patch)?;
+                self.emit(0x6000)?;
+                self.emit(0x6100)?;
+                self.patch(here, patch, address as usize);
+            }
+            ":call" => {
+                let here = self.here;
+                let address = self.address(here, Patch::Address)?;
+                self.emit(0x2000 | address)?;
+            }
+            ":breakpoint" => {
+                self.next()?;
+            }
+            ":monitor" => {
+                self.next()?;
+                self.next()?;
+            }
+            ":assert" => {
+                let message = if self.peek().is_some_and(|text| text.starts_with('"')) {
+                    self.next()?.text[1..].to_string()
+                } else {
+                    "Assertion failed".to_string()
+                };
+                if self.value()? == 0.0 {
+                    return self.error(message);
+                }
+            }
+            "clear" => self.emit(0x00E0)?,
+            "return" | ";" => self.emit(0x00EE)?,
+            "exit" => self.emit(0x00FD)?,
+            "lores" => self.emit(0x00FE)?,
+            "hires" => self.emit(0x00FF)?,
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit(0x00C0 | n)?;
+            }
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.emit(0x00D0 | n)?;
+            }
+            "scroll-right" => self.emit(0x00FB)?,
+            "scroll-left" => self.emit(0x00FC)?,
+            "audio" => self.emit(0xF002)?,
+            "plane" => {
+                let n = self.nibble()?;
+                self.emit(0xF001 | n << 8)?;
+            }
+            "jump" | "jump0" | "native" => {
+                let here = self.here;
+                let address = self.address(here, Patch::Address)?;
+                let prefix = match token.text.as_str() {
+                    "jump" => 0x1000,
+                    "jump0" => 0xB000,
+                    _ => 0x0000,
+                };
+                self.emit(prefix | address)?;
+            }
+            "sprite" => {
+                let x = self.register()? as u16;
+                let y = self.register()? as u16;
+                let n = self.nibble()?;
+                self.emit(0xD000 | x << 8 | y << 4 | n)?;
+            }
+            "bcd" => self.register_operation(0xF033)?,
+            "saveflags" => self.register_operation(0xF075)?,
+            "loadflags" => self.register_operation(0xF085)?,
+            "save" | "load" => {
+                let x = self.register()? as u16;
+                if self.peek() == Some("-") {
+                    self.next()?;
+                    let y = self.register()? as u16;
+                    let suffix = if token.text == "save" { 0x2 } else { 0x3 };
+                    self.emit(0x5000 | x << 8 | y << 4 | suffix)?;
+                } else {
+                    let suffix = if token.text == "save" { 0x55 } else { 0x65 };
+                    self.emit(0xF000 | x << 8 | suffix)?;
+                }
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let suffix = match token.text.as_str() {
+                    "delay" => 0x15,
+                    "buzzer" => 0x18,
+                    _ => 0x3A,
+                };
+                self.register_operation(0xF000 | suffix)?;
+            }
+            "i" => self.i_statement()?,
+            "if" => self.if_statement()?,
+            "else" => {
+                let branch = match self.branches.pop() {
+                    Some(branch) => branch,
+                    None => return self.error("'else' without 'begin'".to_string()),
+                };
+                let here = self.here;
+                self.emit(0x1000)?;
+                let target = self.here;
+                self.patch(branch, Patch::Address, target);
+                self.branches.push(here);
+            }
+            "end" => {
+                let branch = match self.branches.pop() {
+                    Some(branch) => branch,
+                    None => return self.error("'end' without 'begin'".to_string()),
+                };
+                let target = self.here;
+                self.patch(branch, Patch::Address, target);
+            }
+            "loop" => {
+                let here = self.here;
+                self.loops.push((here, Vec::new()));
+            }
+            "while" => {
+                if self.loops.is_empty() {
+                    return self.error("'while' outside of a loop".to_string());
+                }
+                let condition = self.condition()?;
+                self.emit_condition(condition, true)?;
+                let here = self.here;
+                self.emit(0x1000)?;
+                self.loops.last_mut().unwrap().1.push(here);
+            }
+            "again" => {
+                let (start, whiles) = match self.loops.pop() {
+                    Some(frame) => frame,
+                    None => return self.error("'again' without 'loop'".to_string()),
+                };
+                self.emit(0x1000 | start as u16)?;
+                let target = self.here;
+                for jump in whiles {
+                    self.patch(jump, Patch::Address, target);
+                }
+            }
+            text => {
+                if let Some(value) = parse_number(text) {
+                    if !(-128.0..=255.0).contains(&value) {
+                        return self.error(format!("Value {} doesn't fit in a byte", value));
+                    }
+                    return self.emit_byte(value as i64 as u8);
+                }
+                if let Some(&value) = self.constants.get(text) {
+                    return self.emit_byte(value as i64 as u8);
+                }
+                if self.macros.contains_key(text) {
+                    return self.expand_macro(text);
+                }
+                if text.starts_with(':') || text == "{" || text == "}" {
+                    return self.error(format!("Unexpected '{}'", text));
+                }
+                // any other name is a call to a subroutine, maybe defined later
+                self.tokens.push_front(token);
+                let here = self.here;
+                let address = self.address(here, Patch::Address)?;
+                self.emit(0x2000 | address)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// `FX__` instructions only taking a register.
+    fn register_operation(&mut self, opcode: u16) -> Result<()> {
+        let x = self.register()? as u16;
+        self.emit(opcode | x << 8)?;
+        Ok(())
+    }
+
+    fn register_statement(&mut self, register: &str) -> Result<()> {
+        let x = parse_register(register).unwrap_or_else(|| self.aliases[register]) as u16;
+        let operator = self.next()?.text;
+        let operand = self.next()?.text;
+
+        if self.is_register(&operand) {
+            let y = parse_register(&operand).unwrap_or_else(|| self.aliases[&operand]) as u16;
+            let suffix = match operator.as_str() {
+                ":=" => 0x0,
+                "|=" => 0x1,
+                "&=" => 0x2,
+                "^=" => 0x3,
+                "+=" => 0x4,
+                "-=" => 0x5,
+                ">>=" => 0x6,
+                "=-" => 0x7,
+                "<<=" => 0xE,
+                _ => return self.error(format!("Unknown operator '{}'", operator)),
+            };
+            self.emit(0x8000 | x << 8 | y << 4 | suffix)?;
+            return Ok(());
+        }
+
+        match (operator.as_str(), operand.as_str()) {
+            (":=", "key") => self.emit(0xF00A | x << 8)?,
+            (":=", "delay") => self.emit(0xF007 | x << 8)?,
+            (":=", "random") => {
+                let nn = self.byte()? as u16;
+                self.emit(0xC000 | x << 8 | nn)?;
+            }
+            (":=", _) | ("+=", _) | ("-=", _) => {
+                self.tokens.push_front(Token {
+                    text: operand,
+                    line: self.line,
+                });
+                let nn = self.byte()?;
+                match operator.as_str() {
+                    ":=" => self.emit(0x6000 | x << 8 | nn as u16)?,
+                    "+=" => self.emit(0x7000 | x << 8 | nn as u16)?,
+                    _ => self.emit(0x7000 | x << 8 | nn.wrapping_neg() as u16)?,
+                }
+            }
+            _ => return self.error(format!("Unknown operator '{}'", operator)),
+        }
+
+        Ok(())
+    }
+
+    fn i_statement(&mut self) -> Result<()> {
+        let operator = self.next()?.text;
+
+        match operator.as_str() {
+            ":=" => match self.peek() {
+                Some("hex") => {
+                    self.next()?;
+                    self.register_operation(0xF029)?;
+                }
+                Some("bighex") => {
+                    self.next()?;
+                    self.register_operation(0xF030)?;
+                }
+                Some("long") => {
+                    self.next()?;
+                    let here = self.here;
+                    let address = self.address(here + 2, Patch::Long)?;
+                    self.emit(0xF000)?;
+                    self.emit(address)?;
+                }
+                _ => {
+                    let here = self.here;
+                    let address = self.address(here, Patch::Address)?;
+                    self.emit(0xA000 | address)?;
+                }
+            },
+            "+=" => self.register_operation(0xF01E)?,
+            _ => return self.error(format!("Unknown operator '{}'", operator)),
+        }
+
+        Ok(())
+    }
+
+    fn if_statement(&mut self) -> Result<()> {
+        let condition = self.condition()?;
+        let keyword = self.next()?.text;
+
+        match keyword.as_str() {
+            "then" => self.emit_condition(condition, false),
+            "begin" => {
+                self.emit_condition(condition, true)?;
+                let here = self.here;
+                self.emit(0x1000)?;
+                self.branches.push(here);
+                Ok(())
+            }
+            _ => self.error(format!("Expected 'then' or 'begin', found '{}'", keyword)),
+        }
+    }
+
+    fn condition(&mut self) -> Result<Condition> {
+        let register = self.register()?;
+        let operator = self.next()?.text;
+
+        let operand = match operator.as_str() {
+            "key" | "-key" => Operand::None,
+            "==" | "!=" | "<" | ">" | "<=" | ">=" => {
+                if self.peek().is_some_and(|text| self.is_register(text)) {
+                    Operand::Register(self.register()?)
+                } else {
+                    Operand::Byte(self.byte()?)
+                }
+            }
+            _ => return self.error(format!("Unknown comparison '{}'", operator)),
+        };
+
+        Ok(Condition {
+            register,
+            operator,
+            operand,
+        })
+    }
+
+    /// Emits the skip instruction letting the next instruction run only if the condition holds,
+    /// or only if it doesn't when `negate` is set.
+    fn emit_condition(&mut self, condition: Condition, negate: bool) -> Result<()> {
+        let operator = if negate {
+            match condition.operator.as_str() {
+                "==" => "!=",
+                "!=" => "==",
+                "key" => "-key",
+                "-key" => "key",
+                "<" => ">=",
+                ">=" => "<",
+                ">" => "<=",
+                _ => ">",
+            }
+        } else {
+            condition.operator.as_str()
+        };
+        let x = condition.register as u16;
+
+        match (operator, condition.operand) {
+            ("key", _) => self.emit(0xE0A1 | x << 8)?,
+            ("-key", _) => self.emit(0xE09E | x << 8)?,
+            ("==", Operand::Byte(nn)) => self.emit(0x4000 | x << 8 | nn as u16)?,
+            ("!=", Operand::Byte(nn)) => self.emit(0x3000 | x << 8 | nn as u16)?,
+            ("==", Operand::Register(y)) => self.emit(0x9000 | x << 8 | (y as u16) << 4)?,
+            ("!=", Operand::Register(y)) => self.emit(0x5000 | x << 8 | (y as u16) << 4)?,
+            (operator, operand) => {
+                // VF receives the comparison result through a subtraction
+                match operand {
+                    Operand::Register(y) => self.emit(0x8F00 | (y as u16) << 4)?,
+                    Operand::Byte(nn) => self.emit(0x6F00 | nn as u16)?,
+                    Operand::None => unreachable!(),
+                }
+                match operator {
+                    // VF = VX - operand, without borrow if VX >= operand
+                    "<" | ">=" => self.emit(0x8F07 | x << 4)?,
+                    // VF = operand - VX, without borrow if operand >= VX
+                    _ => self.emit(0x8F05 | x << 4)?,
+                }
+                match operator {
+                    "<" | ">" => self.emit(0x4F00)?,
+                    _ => self.emit(0x4F01)?,
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn define_macro(&mut self) -> Result<()> {
+        let name = self.next()?.text;
+        let mut parameters = Vec::new();
+
+        loop {
+            let token = self.next()?;
+            if token.text == "{" {
+                break;
+            }
+            parameters.push(token.text);
+        }
+
+        let body = self.block()?;
+
+        self.macros.insert(
+            name,
+            Macro {
+                parameters,
+                body,
+                calls: 0,
+            },
+        );
+        Ok(())
+    }
+
+    /// Tokens up to the `}` closing an already consumed `{`.
+    fn block(&mut self) -> Result<Vec<Token>> {
+        let mut body = Vec::new();
+        let mut depth = 0;
+
+        loop {
+            let token = self.next()?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" if depth == 0 => return Ok(body),
+                "}" => depth -= 1,
+                _ => {}
+            }
+            body.push(token);
+        }
+    }
+
+    fn expand_macro(&mut self, name: &str) -> Result<()> {
+        // the name itself may be the last token of an expansion still running
+        let left = self.tokens.len();
+        while self.expansions.last().is_some_and(|&(_, end)| left < end) {
+            self.expansions.pop();
+        }
+        if self.expansions.iter().any(|(expanded, _)| expanded == name) {
+            return self.error(format!("Recursive macro '{}'", name));
+        }
+
+        let parameter_count = self.macros[name].parameters.len();
+        let mut arguments = Vec::new();
+        for _ in 0..parameter_count {
+            arguments.push(self.next()?.text);
+        }
+
+        let line = self.line;
+        let definition = self.macros.get_mut(name).unwrap();
+        let calls = definition.calls.to_string();
+        definition.calls += 1;
+
+        let expansion: Vec<Token> = definition
+            .body
+            .iter()
+            .map(|token| {
+                let text = match definition.parameters.iter().position(|p| *p == token.text) {
+                    Some(index) => arguments[index].clone(),
+                    None if token.text == "CALLS" => calls.clone(),
+                    None => token.text.clone(),
+                };
+                Token { text, line }
+            })
+            .collect();
+
+        let end = self.tokens.len();
+        for token in expansion.into_iter().rev() {
+            self.tokens.push_front(token);
+        }
+        self.expansions.push((name.to_string(), end));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Chip8;
+
+    fn rom(source: &str) -> Vec<u8> {
+        match assemble(source) {
+            Ok(program) => program.rom,
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    fn error(source: &str) -> String {
+        match assemble(source) {
+            Ok(_) => panic!("the source assembled"),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    /// V2 after running `if <condition> then v2 := 1` with V0 = `a` and V1 = `b`.
+    fn holds(condition: &str, a: u8, b: u8) -> bool {
+        let source = format!(
+            ": main v0 := {} v1 := {} v2 := 0 if {} then v2 := 1 loop again",
+            a, b, condition
+        );
+        let (mut chip, _) = Chip8::for_rom(&rom(&source)).unwrap();
+        chip.step_frame();
+        chip.registers()[2] == 1
+    }
+
+    #[test]
+    fn main_first_needs_no_jump() {
+        assert_eq!(rom(": main clear"), [0x00, 0xE0]);
+        assert_eq!(
+            rom(": sub return : main sub"),
+            [0x12, 0x04, 0x00, 0xEE, 0x22, 0x02]
+        );
+    }
+
+    #[test]
+    fn conditions() {
+        assert_eq!(
+            rom(": main if v0 == 5 then v1 := 2"),
+            [0x40, 0x05, 0x61, 0x02]
+        );
+        assert_eq!(
+            rom(": main if v0 != v1 then clear"),
+            [0x50, 0x10, 0x00, 0xE0]
+        );
+        assert_eq!(
+            rom(": main if v3 key begin clear else v0 := 1 end"),
+            [0xE3, 0x9E, 0x12, 0x08, 0x00, 0xE0, 0x12, 0x0A, 0x60, 0x01]
+        );
+        assert_eq!(
+            rom(": main loop v0 += 1 while v0 != 9 again"),
+            [0x70, 0x01, 0x40, 0x09, 0x12, 0x08, 0x12, 0x00]
+        );
+    }
+
+    #[test]
+    fn comparisons() {
+        for &(a, b) in &[(0, 0), (3, 7), (7, 3), (255, 0), (0, 255), (128, 128)] {
+            assert_eq!(holds("v0 < v1", a, b), a < b, "{} < {}", a, b);
+            assert_eq!(holds("v0 > v1", a, b), a > b, "{} > {}", a, b);
+            assert_eq!(holds("v0 <= v1", a, b), a <= b, "{} <= {}", a, b);
+            assert_eq!(holds("v0 >= v1", a, b), a >= b, "{} >= {}", a, b);
+            assert_eq!(holds("v0 < 7", a, b), a < 7, "{} < 7", a);
+            assert_eq!(holds("v0 >= 128", a, b), a >= 128, "{} >= 128", a);
+        }
+    }
+
+    #[test]
+    fn macros() {
+        assert_eq!(
+            rom(":macro twice x { x x } :macro inc { v0 += 1 } : main twice inc"),
+            [0x70, 0x01, 0x70, 0x01]
+        );
+        assert_eq!(
+            rom(":macro count { v0 := CALLS } : main count count count"),
+            [0x60, 0x00, 0x60, 0x01, 0x60, 0x02]
+        );
+    }
+
+    #[test]
+    fn recursive_macros() {
+        assert_eq!(
+            error(":macro a { b }\n:macro b { a }\n: main\n  a\n"),
+            "line 4: Recursive macro 'a'"
+        );
+        assert_eq!(
+            error(":macro self x {\n  v0 := x self x }\n: main self 1"),
+            "line 3: Recursive macro 'self'"
+        );
+    }
+
+    #[test]
+    fn org() {
+        let rom = rom(": main jump main :org 0x206 : data 0xAB");
+        assert_eq!(rom, [0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0xAB]);
+
+        let program = assemble(": main i := data :org 0x300 : data 1").unwrap();
+        assert_eq!(program.rom[..2], [0xA3, 0x00]);
+        assert_eq!(program.rom.len(), 0x101);
+        assert_eq!(program.labels["data"], 0x300);
+    }
+
+    #[test]
+    fn long_addresses() {
+        assert_eq!(
+            rom(": main i := long data :org 0x1234 : data 7")[..4],
+            [0xF0, 0x00, 0x12, 0x34]
+        );
+        assert_eq!(rom(": main i := long 0xFFFF"), [0xF0, 0x00, 0xFF, 0xFF]);
+        assert_eq!(
+            error(": main i := 0x1000"),
+            "line 1: Address 0x1000 is out of range"
+        );
+    }
+
+    #[test]
+    fn errors_have_line_numbers() {
+        assert_eq!(
+            error(": main\n  v0 := 1\n  v1 := 300\n"),
+            "line 3: Value 300 doesn't fit in a byte"
+        );
+        assert_eq!(
+            error(": main\n\n  jump nowhere\n"),
+            "line 3: Undefined name 'nowhere'"
+        );
+        assert_eq!(
+            error("clear\n"),
+            "line 1: This program is missing a 'main' label"
+        );
+        assert_eq!(
+            error(": main\n  if v0 == 1 begin\n  clear\n"),
+            "line 3: Missing 'end'"
+        );
+        assert_eq!(error(": main\n  v0 ?= 1"), "line 2: Unknown operator '?='");
+    }
+}
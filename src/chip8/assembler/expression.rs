@@ -0,0 +1,202 @@
+use super::{parse_number, Assembler, Result, Token};
+
+const BINARY_OPERATORS: [&str; 19] = [
+    "-", "+", "*", "/", "%", "&", "|", "^", "<<", ">>", "pow", "min", "max", "<", "<=", ">", ">=",
+    "==", "!=",
+];
+
+const UNARY_OPERATORS: [&str; 13] = [
+    "-", "~", "!", "sin", "cos", "tan", "exp", "log", "abs", "sqrt", "sign", "ceil", "floor",
+];
+
+impl Assembler {
+    /// Evaluates a `{ }` expression whose opening brace is already consumed.
+    /// Like Octo, there is no operator precedence:
+    /// expressions are evaluated from right to left unless parentheses say otherwise.
+    pub(super) fn expression(&mut self) -> Result<f64> {
+        let tokens = self.block()?;
+        let mut position = 0;
+        let value = self.binary(&tokens, &mut position)?;
+
+        if position < tokens.len() {
+            return self.error(format!(
+                "Unexpected '{}' in expression",
+                tokens[position].text
+            ));
+        }
+        Ok(value)
+    }
+
+    fn binary(&self, tokens: &[Token], position: &mut usize) -> Result<f64> {
+        let left = self.unary(tokens, position)?;
+
+        let operator = match tokens.get(*position) {
+            Some(token) if BINARY_OPERATORS.contains(&token.text.as_str()) => token.text.as_str(),
+            _ => return Ok(left),
+        };
+        *position += 1;
+        let right = self.binary(tokens, position)?;
+
+        let boolean = |condition: bool| if condition { 1.0 } else { 0.0 };
+
+        Ok(match operator {
+            "-" => left - right,
+            "+" => left + right,
+            "*" => left * right,
+            "/" => {
+                if right == 0.0 {
+                    return self.error("Division by zero".to_string());
+                }
+                left / right
+            }
+            "%" => left % right,
+            "&" => (left as i64 & right as i64) as f64,
+            "|" => (left as i64 | right as i64) as f64,
+            "^" => (left as i64 ^ right as i64) as f64,
+            "<<" => ((left as i64) << right as i64) as f64,
+            ">>" => (left as i64 >> right as i64) as f64,
+            "pow" => left.powf(right),
+            "min" => left.min(right),
+            "max" => left.max(right),
+            "<" => boolean(left < right),
+            "<=" => boolean(left <= right),
+            ">" => boolean(left > right),
+            ">=" => boolean(left >= right),
+            "==" => boolean(left == right),
+            _ => boolean(left != right),
+        })
+    }
+
+    fn unary(&self, tokens: &[Token], position: &mut usize) -> Result<f64> {
+        let token = match tokens.get(*position) {
+            Some(token) => token,
+            None => return self.error("Incomplete expression".to_string()),
+        };
+        *position += 1;
+
+        if token.text == "(" {
+            let value = self.binary(tokens, position)?;
+            match tokens.get(*position) {
+                Some(token) if token.text == ")" => *position += 1,
+                _ => return self.error("Missing ')' in expression".to_string()),
+            }
+            return Ok(value);
+        }
+
+        if token.text == "@" {
+            let address = self.unary(tokens, position)? as usize;
+            return match self.rom.get(address) {
+                Some(byte) => Ok(*byte as f64),
+                None => self.error(format!("Address 0x{:X} is out of range", address)),
+            };
+        }
+
+        if UNARY_OPERATORS.contains(&token.text.as_str()) {
+            let value = self.unary(tokens, position)?;
+            return Ok(match token.text.as_str() {
+                "-" => -value,
+                "~" => !(value as i64) as f64,
+                "!" => {
+                    if value == 0.0 {
+                        1.0
+                    } else {
+                        0.0
+                    }
+                }
+                "sin" => value.sin(),
+                "cos" => value.cos(),
+                "tan" => value.tan(),
+                "exp" => value.exp(),
+                "log" => value.ln(),
+                "abs" => value.abs(),
+                "sqrt" => value.sqrt(),
+                "sign" => value.signum(),
+                "ceil" => value.ceil(),
+                _ => value.floor(),
+            });
+        }
+
+        self.atom(&token.text)
+    }
+
+    fn atom(&self, text: &str) -> Result<f64> {
+        if let Some(value) = parse_number(text) {
+            return Ok(value);
+        }
+
+        match text {
+            "PI" => Ok(std::f64::consts::PI),
+            "E" => Ok(std::f64::consts::E),
+            "HERE" => Ok(self.here as f64),
+            _ => match (self.constants.get(text), self.labels.get(text)) {
+                (Some(value), _) => Ok(*value),
+                (None, Some(address)) => Ok(*address as f64),
+                (None, None) => self.error(format!("Undefined name '{}'", text)),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::assemble;
+
+    /// The byte of `:calc x { expression } :byte x`.
+    fn calc(expression: &str) -> Result<u8, String> {
+        assemble(&format!(": main :calc x {{ {} }} :byte x", expression))
+            .map(|program| program.rom[0])
+            .map_err(|error| error.to_string())
+    }
+
+    #[test]
+    fn right_to_left_without_precedence() {
+        assert_eq!(calc("2 * 3 + 1"), Ok(8));
+        assert_eq!(calc("( 2 * 3 ) + 1"), Ok(7));
+        assert_eq!(calc("10 - 2 - 3"), Ok(11));
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(calc("0x0F & 0x3C"), Ok(0x0C));
+        assert_eq!(calc("1 << 4 | 1"), Ok(0x20));
+        assert_eq!(calc("7 % 4"), Ok(3));
+        assert_eq!(calc("3 max 9"), Ok(9));
+        assert_eq!(calc("2 pow 7"), Ok(128));
+        assert_eq!(calc("3 < 4"), Ok(1));
+        assert_eq!(calc("3 == 4"), Ok(0));
+        assert_eq!(calc("- 5 + 6"), Ok(1));
+        assert_eq!(calc("floor 7 / 2"), Ok(3));
+        assert_eq!(calc("! 0"), Ok(1));
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(calc("HERE - 0x200"), Ok(0x00));
+        let program =
+            assemble(": main :const W 8 :calc x { W * 2 } :byte x :byte { HERE - 0x200 }");
+        assert_eq!(program.unwrap().rom, [16, 0x01]);
+        let program = assemble(": main :byte 0x42 :byte { @ 0x200 + 1 }");
+        assert_eq!(program.unwrap().rom, [0x42, 0x43]);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(calc("1 / 0"), Err("line 1: Division by zero".to_string()));
+        assert_eq!(
+            calc("( 1 + 2"),
+            Err("line 1: Missing ')' in expression".to_string())
+        );
+        assert_eq!(
+            calc("1 +"),
+            Err("line 1: Incomplete expression".to_string())
+        );
+        assert_eq!(
+            calc("2 3"),
+            Err("line 1: Unexpected '3' in expression".to_string())
+        );
+        assert_eq!(
+            calc("nothing"),
+            Err("line 1: Undefined name 'nothing'".to_string())
+        );
+    }
+}
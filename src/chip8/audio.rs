@@ -0,0 +1,154 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use hound::{SampleFormat, WavSpec, WavWriter};
+
+use super::FREQUENCY;
+
+pub const SAMPLE_RATE: u32 = 44100;
+pub const SAMPLES_PER_FRAME: usize = (SAMPLE_RATE / FREQUENCY) as usize;
+
+/// The square wave of the buzzer before any XO-CHIP pattern is loaded, 500 Hz at the default pitch.
+pub const DEFAULT_PATTERN: [u8; 16] = [0xF0; 16];
+pub const DEFAULT_PITCH: u8 = 64;
+
+const AMPLITUDE: i16 = 8000;
+
+/// Where the samples go.
+enum Output {
+    Wav(WavWriter<BufWriter<File>>),
+    /// Kept until `take_samples`, for the hosts playing the sound themselves.
+    Samples(Vec<i16>),
+}
+
+/// Plays the sound of the emulated time into a WAV file or into memory.
+///
+/// The sound plays while the sound timer isn't 0: it's a 1-bit pattern of 128 samples
+/// read at 4000 * 2^((pitch - 64) / 48) samples per second, as on XO-CHIP.
+/// The changes happen at the sample of the frame where the instruction was executed,
+/// so the file doesn't depend on how fast the emulation ran.
+pub struct AudioRecorder {
+    output: Output,
+    /// Empty in memory.
+    path: PathBuf,
+    is_playing: bool,
+    pattern: [u8; 16],
+    pitch: u8,
+    /// Position in the pattern, in bits.
+    position: f64,
+    /// Samples of the current frame already written.
+    sample: usize,
+}
+
+impl AudioRecorder {
+    pub fn create(path: &Path) -> Result<AudioRecorder, String> {
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: SAMPLE_RATE,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let writer = WavWriter::create(path, spec)
+            .map_err(|error| format!("cannot create '{}': {}", path.display(), error))?;
+
+        Ok(AudioRecorder::with_output(Output::Wav(writer), path))
+    }
+
+    /// Keeps the samples, 16-bit mono at `SAMPLE_RATE`, until `take_samples`.
+    pub fn in_memory() -> AudioRecorder {
+        AudioRecorder::with_output(Output::Samples(Vec::new()), Path::new(""))
+    }
+
+    fn with_output(output: Output, path: &Path) -> AudioRecorder {
+        AudioRecorder {
+            output,
+            path: path.to_path_buf(),
+            is_playing: false,
+            pattern: DEFAULT_PATTERN,
+            pitch: DEFAULT_PITCH,
+            position: 0.,
+            sample: 0,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The sound starts or stops at `sample` of the current frame.
+    pub fn set_playing(&mut self, sample: usize, is_playing: bool) -> Result<(), String> {
+        self.play(sample)?;
+        self.is_playing = is_playing;
+        Ok(())
+    }
+
+    pub fn set_pattern(&mut self, sample: usize, pattern: [u8; 16]) -> Result<(), String> {
+        self.play(sample)?;
+        self.pattern = pattern;
+        Ok(())
+    }
+
+    pub fn set_pitch(&mut self, sample: usize, pitch: u8) -> Result<(), String> {
+        self.play(sample)?;
+        self.pitch = pitch;
+        Ok(())
+    }
+
+    /// Writes the rest of the frame, the sound plays in the next one if `is_playing`.
+    pub fn end_frame(&mut self, is_playing: bool) -> Result<(), String> {
+        self.play(SAMPLES_PER_FRAME)?;
+        self.sample = 0;
+        self.is_playing = is_playing;
+        Ok(())
+    }
+
+    /// The samples played since the last call, none for a WAV file.
+    pub fn take_samples(&mut self) -> Vec<i16> {
+        match self.output {
+            Output::Samples(ref mut samples) => std::mem::take(samples),
+            Output::Wav(_) => Vec::new(),
+        }
+    }
+
+    /// Writes the size of the samples in the header.
+    pub fn finish(self) -> Result<(), String> {
+        let path = self.path;
+        match self.output {
+            Output::Wav(writer) => writer
+                .finalize()
+                .map_err(|error| format!("cannot write '{}': {}", path.display(), error)),
+            Output::Samples(_) => Ok(()),
+        }
+    }
+
+    /// Writes the samples of the frame until `sample` with the current sound.
+    fn play(&mut self, sample: usize) -> Result<(), String> {
+        let bits_per_sample =
+            4000. * 2f64.powf((self.pitch as f64 - 64.) / 48.) / SAMPLE_RATE as f64;
+
+        for _ in self.sample..sample.min(SAMPLES_PER_FRAME) {
+            let value = if self.is_playing {
+                let bit = self.position as usize;
+                self.position = (self.position + bits_per_sample) % 128.;
+                if self.pattern[bit / 8] & (0x80 >> (bit % 8)) != 0 {
+                    AMPLITUDE
+                } else {
+                    -AMPLITUDE
+                }
+            } else {
+                0
+            };
+
+            match self.output {
+                Output::Wav(ref mut writer) => writer.write_sample(value).map_err(|error| {
+                    format!("cannot write '{}': {}", self.path.display(), error)
+                })?,
+                Output::Samples(ref mut samples) => samples.push(value),
+            }
+        }
+
+        self.sample = self.sample.max(sample.min(SAMPLES_PER_FRAME));
+        Ok(())
+    }
+}
@@ -0,0 +1,314 @@
+use std::collections::hash_map::{Entry, HashMap};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+
+use super::headless_interface::HeadlessInterface;
+use super::Chip8;
+
+// the runs are spread on threads, so the emulator mustn't hold thread-bound state
+const _: fn() = || {
+    fn assert_send<T: Send>() {}
+    assert_send::<Chip8<HeadlessInterface>>();
+};
+
+/// The keys held from some frames on, like `0 5` to hold 5 from the start.
+///
+/// ```text
+/// # frame, then the keys held from it in hexadecimal, or - for none
+/// 0 5
+/// 30 -
+/// 60 46
+/// ```
+#[derive(Clone, Default, Debug)]
+pub struct InputScript {
+    /// Frames in order and the keys held from them, a bit per key.
+    changes: Vec<(u64, u16)>,
+}
+
+impl InputScript {
+    /// The keys held during `frame`.
+    pub fn keys(&self, frame: u64) -> u16 {
+        match self.changes.partition_point(|&(start, _)| start <= frame) {
+            0 => 0,
+            index => self.changes[index - 1].1,
+        }
+    }
+}
+
+impl FromStr for InputScript {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<InputScript, String> {
+        let mut changes: Vec<(u64, u16)> = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message: String| format!("line {}: {}", number + 1, message);
+
+            let mut fields = line.split_whitespace();
+            let (frame, keys) = match (fields.next(), fields.next(), fields.next()) {
+                (Some(frame), Some(keys), None) => (frame, keys),
+                _ => return Err(error(format!("\"{}\" isn't a frame and keys", line))),
+            };
+            let frame: u64 = frame
+                .parse()
+                .map_err(|_| error(format!("invalid frame \"{}\"", frame)))?;
+            let keys = if keys == "-" {
+                0
+            } else {
+                keys.chars().try_fold(0, |mask, key| {
+                    key.to_digit(16)
+                        .map(|key| mask | 1 << key)
+                        .ok_or_else(|| error(format!("invalid keypad key '{}'", key)))
+                })?
+            };
+
+            if changes.last().is_some_and(|&(last, _)| last >= frame) {
+                return Err(error(format!(
+                    "the frame {} isn't after the previous one",
+                    frame
+                )));
+            }
+            changes.push((frame, keys));
+        }
+        Ok(InputScript { changes })
+    }
+}
+
+/// A run of a ROM, independent of all the others.
+#[derive(Clone)]
+pub struct Job {
+    /// The ROM in the reports.
+    pub name: String,
+    /// Shared by the runs of the same ROM.
+    pub rom: Arc<Vec<u8>>,
+    pub seed: u64,
+    pub inputs: Arc<InputScript>,
+    /// The run stops earlier when the program does.
+    pub frames: u64,
+}
+
+impl Job {
+    /// Runs the ROM as fast as possible, and gives the emulator where it stopped
+    /// with the number of frames run.
+    pub fn run(&self) -> Result<(Chip8<HeadlessInterface>, u64), String> {
+        let (mut chip, _) = Chip8::for_rom(&self.rom)?;
+        chip.set_seed(self.seed);
+
+        let mut frame = 0;
+        while frame < self.frames && chip.is_running() {
+            let keys = self.inputs.keys(frame);
+            for key in 0..16 {
+                chip.set_key(key, keys & (1 << key) != 0);
+            }
+            chip.step_frame();
+            frame += 1;
+        }
+        Ok((chip, frame))
+    }
+}
+
+/// A batch of runs, the paths being relative to the file.
+///
+/// ```toml
+/// # the frames of each run, when the program doesn't stop before
+/// frames = 600
+///
+/// [[job]]
+/// rom = "pong.ch8"
+/// # the runs of a job have the seeds from this one on
+/// seed = 1
+/// runs = 1000
+/// inputs = "serve.txt"
+///
+/// [[job]]
+/// rom = "maze.ch8"
+/// frames = 60
+/// ```
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct BatchFile {
+    pub frames: u64,
+    #[serde(default, rename = "job")]
+    pub jobs: Vec<JobSpec>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct JobSpec {
+    pub rom: PathBuf,
+    #[serde(default)]
+    pub seed: u64,
+    #[serde(default = "default_runs")]
+    pub runs: u64,
+    /// An `InputScript`, no key is pressed without it.
+    pub inputs: Option<PathBuf>,
+    pub frames: Option<u64>,
+}
+
+fn default_runs() -> u64 {
+    1
+}
+
+impl BatchFile {
+    pub fn load(path: &Path) -> Result<BatchFile, String> {
+        std::fs::read_to_string(path)
+            .map_err(|error| error.to_string())
+            .and_then(|text| toml::from_str(&text).map_err(|error| error.to_string()))
+            .map_err(|error| format!("cannot read '{}': {}", path.display(), error.trim_end()))
+    }
+
+    /// The runs of all the jobs, reading their files from `directory`.
+    pub fn jobs(&self, directory: &Path) -> Result<Vec<Job>, String> {
+        let mut roms: HashMap<&Path, Arc<Vec<u8>>> = HashMap::new();
+        let mut jobs = Vec::new();
+        for spec in &self.jobs {
+            let rom = match roms.entry(&spec.rom) {
+                Entry::Occupied(rom) => Arc::clone(rom.get()),
+                Entry::Vacant(entry) => {
+                    let path = directory.join(&spec.rom);
+                    let rom = std::fs::read(&path)
+                        .map_err(|error| format!("cannot read '{}': {}", path.display(), error))?;
+                    Arc::clone(entry.insert(Arc::new(rom)))
+                }
+            };
+            let inputs = match spec.inputs {
+                Some(ref inputs) => {
+                    let path = directory.join(inputs);
+                    std::fs::read_to_string(&path)
+                        .map_err(|error| error.to_string())
+                        .and_then(|text| text.parse())
+                        .map_err(|error| format!("cannot read '{}': {}", path.display(), error))?
+                }
+                None => InputScript::default(),
+            };
+            let inputs = Arc::new(inputs);
+
+            jobs.extend((0..spec.runs).map(|run| Job {
+                name: spec.rom.to_string_lossy().into_owned(),
+                rom: Arc::clone(&rom),
+                seed: spec.seed.wrapping_add(run),
+                inputs: Arc::clone(&inputs),
+                frames: spec.frames.unwrap_or(self.frames),
+            }));
+        }
+        Ok(jobs)
+    }
+}
+
+/// Lowercase hexadecimal SHA-1, to compare the runs.
+pub fn hash(bytes: &[u8]) -> String {
+    Sha1::digest(bytes)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Runs the jobs on `threads` threads, and gives what `collect` kept of each run
+/// from its index, the emulator and the frames run, in the order of the jobs.
+pub fn run_all<T, F>(jobs: &[Job], threads: usize, collect: F) -> Vec<Result<T, String>>
+where
+    T: Send,
+    F: Fn(usize, &Chip8<HeadlessInterface>, u64) -> T + Sync,
+{
+    let next_job = AtomicUsize::new(0);
+    let run_jobs = || {
+        let mut results = Vec::new();
+        loop {
+            let index = next_job.fetch_add(1, Ordering::Relaxed);
+            let job = match jobs.get(index) {
+                Some(job) => job,
+                None => return results,
+            };
+            let result = job
+                .run()
+                .map(|(chip, frames)| collect(index, &chip, frames));
+            results.push((index, result));
+        }
+    };
+
+    let mut results: Vec<(usize, Result<T, String>)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.clamp(1, jobs.len().max(1)))
+            .map(|_| scope.spawn(run_jobs))
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("a batch thread panicked"))
+            .collect()
+    });
+    results.sort_by_key(|&(index, _)| index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// The threads of the machine, one if unknown.
+pub fn default_threads() -> usize {
+    thread::available_parallelism().map_or(1, |threads| threads.get())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn input_script() {
+        let inputs: InputScript = "# hold 4, then 6 and F\n0 4\n\n30 -  # none\n60 6f\n"
+            .parse()
+            .unwrap();
+        assert_eq!(inputs.keys(0), 1 << 4);
+        assert_eq!(inputs.keys(29), 1 << 4);
+        assert_eq!(inputs.keys(30), 0);
+        assert_eq!(inputs.keys(1000), 1 << 6 | 1 << 0xF);
+        assert_eq!("10 1".parse::<InputScript>().unwrap().keys(9), 0);
+
+        let error = |text: &str| text.parse::<InputScript>().unwrap_err();
+        assert_eq!(
+            error("0 1\n30 2\n30 3"),
+            "line 3: the frame 30 isn't after the previous one"
+        );
+        assert_eq!(
+            error("5 1\n2 3"),
+            "line 2: the frame 2 isn't after the previous one"
+        );
+        assert_eq!(error("0 G"), "line 1: invalid keypad key 'G'");
+        assert_eq!(error("0"), "line 1: \"0\" isn't a frame and keys");
+        assert_eq!(error("-1 2"), "line 1: invalid frame \"-1\"");
+    }
+
+    #[test]
+    fn seeded_runs() {
+        // draws random numbers in a loop
+        let rom = Arc::new(vec![0xC0, 0xFF, 0xC1, 0xFF, 0x72, 0x01, 0x12, 0x00]);
+        let inputs = Arc::new(InputScript::default());
+        let jobs: Vec<Job> = (0..8)
+            .map(|run| Job {
+                name: "random".to_string(),
+                rom: Arc::clone(&rom),
+                seed: run % 4,
+                inputs: Arc::clone(&inputs),
+                frames: 20,
+            })
+            .collect();
+        let run = |threads| -> Vec<String> {
+            run_all(&jobs, threads, |_, chip, frames| {
+                assert_eq!(frames, 20);
+                hash(&chip.save_state())
+            })
+            .into_iter()
+            .collect::<Result<_, _>>()
+            .unwrap()
+        };
+
+        let states = run(1);
+        assert_eq!(states, run(4));
+        assert_eq!(states[..4], states[4..]);
+        assert_ne!(states[0], states[1]);
+    }
+}
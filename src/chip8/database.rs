@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+
+use super::graphic_engine::{Keymap, Palette};
+use super::quirks::{Platform, Quirks};
+
+/// Programs in the format of the `programs.json` file of the community CHIP-8 database
+/// (https://github.com/chip-8/chip-8-database), so the upstream file can replace it as is.
+/// Only a sample is embedded for now, the other ROMs fall back to the guessed platform.
+const PROGRAMS: &str = include_str!("database/programs.json");
+
+#[derive(Deserialize)]
+struct Program {
+    title: String,
+    #[serde(default)]
+    release: Option<String>,
+    #[serde(default)]
+    authors: Vec<String>,
+    roms: HashMap<String, Rom>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Rom {
+    #[serde(default)]
+    platforms: Vec<String>,
+    #[serde(default)]
+    quirky_platforms: HashMap<String, HashMap<String, bool>>,
+    #[serde(default)]
+    tickrate: Option<u32>,
+    #[serde(default)]
+    colors: Option<Colors>,
+    #[serde(default)]
+    keys: HashMap<String, u8>,
+}
+
+#[derive(Deserialize)]
+struct Colors {
+    #[serde(default)]
+    pixels: Vec<String>,
+}
+
+/// What the database knows about a ROM.
+#[derive(Clone)]
+pub struct RomInfo {
+    pub title: String,
+    pub release: Option<String>,
+    pub authors: Vec<String>,
+    pub platform: Option<Platform>,
+    pub quirks: Option<Quirks>,
+    /// Instructions per frame.
+    pub speed: Option<u32>,
+    pub palette: Option<Palette>,
+    pub keymap: Option<Keymap>,
+}
+
+impl RomInfo {
+    /// `Title (Author, release)`, for window titles and logs.
+    pub fn description(&self) -> String {
+        let mut credits = self.authors.join(", ");
+        if let Some(ref release) = self.release {
+            if !credits.is_empty() {
+                credits.push_str(", ");
+            }
+            credits.push_str(release);
+        }
+
+        if credits.is_empty() {
+            self.title.clone()
+        } else {
+            format!("{} ({})", self.title, credits)
+        }
+    }
+}
+
+/// Lowercase hexadecimal SHA-1 of a ROM, the key of the database.
+pub fn rom_hash(rom: &[u8]) -> String {
+    Sha1::digest(rom)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Identifies a ROM by its hash in the embedded database.
+pub fn lookup(rom: &[u8]) -> Option<RomInfo> {
+    static ROMS: OnceLock<HashMap<String, RomInfo>> = OnceLock::new();
+    ROMS.get_or_init(roms).get(&rom_hash(rom)).cloned()
+}
+
+/// The ROMs of the database by their hash, parsed once.
+fn roms() -> HashMap<String, RomInfo> {
+    let programs: Vec<Program> = serde_json::from_str(PROGRAMS).expect("invalid ROM database");
+    let mut roms = HashMap::new();
+
+    for program in programs {
+        for (hash, rom) in &program.roms {
+            // the first platform of the list is the one the ROM was written for
+            let (platform, quirks) =
+                match rom.platforms.iter().find_map(|name| {
+                    platform(name).map(|(platform, quirks)| (name, platform, quirks))
+                }) {
+                    Some((name, platform, mut quirks)) => {
+                        if let Some(overrides) = rom.quirky_platforms.get(name) {
+                            apply_quirks(&mut quirks, overrides);
+                        }
+                        (Some(platform), Some(quirks))
+                    }
+                    None => (None, None),
+                };
+
+            roms.entry(hash.clone()).or_insert_with(|| RomInfo {
+                title: program.title.clone(),
+                release: program.release.clone(),
+                authors: program.authors.clone(),
+                platform,
+                quirks,
+                speed: rom.tickrate,
+                palette: rom.colors.as_ref().and_then(palette),
+                keymap: keymap(&rom.keys),
+            });
+        }
+    }
+    roms
+}
+
+/// The database names the exact machine, each one having its own quirks.
+fn platform(name: &str) -> Option<(Platform, Quirks)> {
+    match name {
+        "originalChip8" | "hybridVIP" => Some((Platform::Chip8, Platform::Chip8.quirks())),
+        "modernChip8" => Some((
+            Platform::Chip8,
+            Quirks {
+                vf_reset: false,
+                display_wait: false,
+                ..Platform::Chip8.quirks()
+            },
+        )),
+        "chip48" | "superchip1" | "superchip" => {
+            Some((Platform::SuperChip, Platform::SuperChip.quirks()))
+        }
+        "xochip" => Some((Platform::XoChip, Platform::XoChip.quirks())),
+        _ => None,
+    }
+}
+
+/// Quirk names of the database, the ones with no equivalent here are ignored.
+fn apply_quirks(quirks: &mut Quirks, overrides: &HashMap<String, bool>) {
+    for (name, &enabled) in overrides {
+        match name.as_str() {
+            "logic" => quirks.vf_reset = enabled,
+            "memoryLeaveIUnchanged" => quirks.memory = !enabled,
+            "vblank" => quirks.display_wait = enabled,
+            "wrap" => quirks.clipping = !enabled,
+            "shift" => quirks.shifting = enabled,
+            "jump" => quirks.jumping = enabled,
+            _ => {}
+        }
+    }
+}
+
+fn palette(colors: &Colors) -> Option<Palette> {
+    colors.pixels.join(",").parse().ok()
+}
+
+/// The database tells which keypad keys the game uses,
+/// they are moved under WASD and the keys around.
+fn keymap(keys: &HashMap<String, u8>) -> Option<Keymap> {
+    if keys.is_empty() {
+        return None;
+    }
+
+    let mut keymap = Keymap::default();
+    let bindings = [
+        ("up", 'w'),
+        ("left", 'a'),
+        ("down", 's'),
+        ("right", 'd'),
+        ("a", 'e'),
+        ("b", 'q'),
+    ];
+    for (name, keyboard_key) in bindings.iter() {
+        if let Some(&key) = keys.get(*name) {
+            keymap.bind(*keyboard_key, key);
+        }
+    }
+    Some(keymap)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn embedded_database() {
+        assert!(!roms().is_empty());
+
+        let maze = lookup(&[
+            0xA2, 0x1E, 0xC2, 0x01, 0x32, 0x01, 0xA2, 0x1A, 0xD0, 0x14, 0x70, 0x04, 0x30, 0x40,
+            0x12, 0x00, 0x60, 0x00, 0x71, 0x04, 0x31, 0x20, 0x12, 0x00, 0x12, 0x18, 0x80, 0x40,
+            0x20, 0x10, 0x20, 0x40, 0x80, 0x10,
+        ])
+        .unwrap();
+        assert_eq!(maze.description(), "Maze (David Winter, 199x)");
+        assert_eq!(maze.platform, Some(Platform::Chip8));
+        assert_eq!(maze.speed, Some(10));
+        assert!(lookup(&[0x12, 0x00]).is_none());
+    }
+}
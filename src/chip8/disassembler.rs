@@ -0,0 +1,663 @@
+use std::collections::BTreeMap;
+
+use super::opcode::OpCode;
+use super::quirks::Platform;
+use super::OFFSET_USABLE_MEM;
+
+/// Output format of the disassembler.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Syntax {
+    /// Classic mnemonics (`LD V0, 0x05`) prefixed by the address and the raw opcode.
+    Classic,
+    /// Octo source that assembles back into the same ROM.
+    Octo,
+}
+
+/// How the execution continues after an instruction.
+#[derive(Clone, Copy)]
+enum Flow {
+    Next,
+    Skip,
+    Jump(usize),
+    Call(usize),
+    JumpTable(usize),
+    Return,
+    Stop,
+    Invalid,
+}
+
+/// Why an address gets a label, the first variants win when an address is reached in several ways.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum LabelKind {
+    Main,
+    Subroutine,
+    Jump,
+    Table,
+    Data,
+}
+
+/// Decodes one instruction through `OpCode::execute_opcode`,
+/// so the disassembly can never disagree with what the CPU executes.
+struct Decoder<'a> {
+    syntax: Syntax,
+    labels: &'a BTreeMap<usize, String>,
+    opcode: u16,
+    next_word: u16, // the operand of F000 NNNN
+    size: usize,
+    platform: Platform, // the first one knowing the instruction
+    text: String,
+    flow: Flow,
+    data_reference: Option<usize>,
+}
+
+impl<'a> Decoder<'a> {
+    fn new(syntax: Syntax, labels: &'a BTreeMap<usize, String>) -> Decoder<'a> {
+        Decoder {
+            syntax,
+            labels,
+            opcode: 0,
+            next_word: 0,
+            size: 2,
+            platform: Platform::Chip8,
+            text: String::new(),
+            flow: Flow::Invalid,
+            data_reference: None,
+        }
+    }
+
+    fn decode(&mut self, opcode: u16, next_word: u16) {
+        self.opcode = opcode;
+        self.next_word = next_word;
+        self.size = 2;
+        self.platform = Platform::Chip8;
+        self.text.clear();
+        self.flow = Flow::Next;
+        self.data_reference = None;
+        self.execute_opcode(opcode);
+    }
+
+    fn address(&self, address: usize) -> String {
+        match self.labels.get(&address) {
+            Some(label) => label.clone(),
+            None => format!("0x{:03X}", address),
+        }
+    }
+
+    fn emit(&mut self, classic: String, octo: String) {
+        self.text = match self.syntax {
+            Syntax::Classic => classic,
+            Syntax::Octo => octo,
+        };
+    }
+
+    /// Octo has no syntax for this exact encoding, so the bytes are written as they are.
+    fn raw_octo(&self) -> String {
+        format!("0x{:02X} 0x{:02X}", self.opcode >> 8, self.opcode & 0xFF)
+    }
+
+    fn extension(&mut self, platform: Platform, classic: &str, octo: &str) {
+        self.platform = platform;
+        self.emit(classic.to_string(), octo.to_string());
+    }
+
+    fn skip_if(&mut self, classic: String, octo_condition: String) {
+        self.flow = Flow::Skip;
+        self.emit(classic, format!("if {} then", octo_condition));
+    }
+}
+
+impl OpCode for Decoder<'_> {
+    fn op1(&mut self) {
+        self.flow = Flow::Stop;
+        let octo = self.raw_octo();
+        self.emit(format!("SYS 0x{:03X}", self.opcode & 0x0FFF), octo);
+    }
+    fn op2(&mut self) {
+        self.emit("CLS".to_string(), "clear".to_string());
+    }
+    fn op3(&mut self) {
+        self.flow = Flow::Return;
+        self.emit("RET".to_string(), "return".to_string());
+    }
+    fn op4(&mut self, nnn: usize) {
+        self.flow = Flow::Jump(nnn);
+        let target = self.address(nnn);
+        self.emit(format!("JP {}", target), format!("jump {}", target));
+    }
+    fn op5(&mut self, nnn: usize) {
+        self.flow = Flow::Call(nnn);
+        let target = self.address(nnn);
+        self.emit(format!("CALL {}", target), format!(":call {}", target));
+    }
+    fn op6(&mut self, x: usize, nn: u8) {
+        self.skip_if(
+            format!("SE V{:X}, 0x{:02X}", x, nn),
+            format!("v{:x} != 0x{:02X}", x, nn),
+        );
+    }
+    fn op7(&mut self, x: usize, nn: u8) {
+        self.skip_if(
+            format!("SNE V{:X}, 0x{:02X}", x, nn),
+            format!("v{:x} == 0x{:02X}", x, nn),
+        );
+    }
+    fn op8(&mut self, x: usize, y: usize) {
+        self.skip_if(
+            format!("SE V{:X}, V{:X}", x, y),
+            format!("v{:x} != v{:x}", x, y),
+        );
+    }
+    fn op9(&mut self, x: usize, nn: u8) {
+        self.emit(
+            format!("LD V{:X}, 0x{:02X}", x, nn),
+            format!("v{:x} := 0x{:02X}", x, nn),
+        );
+    }
+    fn op10(&mut self, x: usize, nn: u8) {
+        self.emit(
+            format!("ADD V{:X}, 0x{:02X}", x, nn),
+            format!("v{:x} += 0x{:02X}", x, nn),
+        );
+    }
+    fn op11(&mut self, x: usize, y: usize) {
+        self.emit(
+            format!("LD V{:X}, V{:X}", x, y),
+            format!("v{:x} := v{:x}", x, y),
+        );
+    }
+    fn op12(&mut self, x: usize, y: usize) {
+        self.emit(
+            format!("OR V{:X}, V{:X}", x, y),
+            format!("v{:x} |= v{:x}", x, y),
+        );
+    }
+    fn op13(&mut self, x: usize, y: usize) {
+        self.emit(
+            format!("AND V{:X}, V{:X}", x, y),
+            format!("v{:x} &= v{:x}", x, y),
+        );
+    }
+    fn op14(&mut self, x: usize, y: usize) {
+        self.emit(
+            format!("XOR V{:X}, V{:X}", x, y),
+            format!("v{:x} ^= v{:x}", x, y),
+        );
+    }
+    fn op15(&mut self, x: usize, y: usize) {
+        self.emit(
+            format!("ADD V{:X}, V{:X}", x, y),
+            format!("v{:x} += v{:x}", x, y),
+        );
+    }
+    fn op16(&mut self, x: usize, y: usize) {
+        self.emit(
+            format!("SUB V{:X}, V{:X}", x, y),
+            format!("v{:x} -= v{:x}", x, y),
+        );
+    }
+    fn op17(&mut self, x: usize, y: usize) {
+        self.emit(
+            format!("SHR V{:X}, V{:X}", x, y),
+            format!("v{:x} >>= v{:x}", x, y),
+        );
+    }
+    fn op18(&mut self, x: usize, y: usize) {
+        self.emit(
+            format!("SUBN V{:X}, V{:X}", x, y),
+            format!("v{:x} =- v{:x}", x, y),
+        );
+    }
+    fn op19(&mut self, x: usize, y: usize) {
+        self.emit(
+            format!("SHL V{:X}, V{:X}", x, y),
+            format!("v{:x} <<= v{:x}", x, y),
+        );
+    }
+    fn op20(&mut self, x: usize, y: usize) {
+        self.skip_if(
+            format!("SNE V{:X}, V{:X}", x, y),
+            format!("v{:x} == v{:x}", x, y),
+        );
+    }
+    fn op21(&mut self, nnn: usize) {
+        self.data_reference = Some(nnn);
+        let target = self.address(nnn);
+        self.emit(format!("LD I, {}", target), format!("i := {}", target));
+    }
+    fn op22(&mut self, nnn: usize) {
+        self.flow = Flow::JumpTable(nnn);
+        let target = self.address(nnn);
+        self.emit(format!("JP V0, {}", target), format!("jump0 {}", target));
+    }
+    fn op23(&mut self, x: usize, nn: u8) {
+        self.emit(
+            format!("RND V{:X}, 0x{:02X}", x, nn),
+            format!("v{:x} := random 0x{:02X}", x, nn),
+        );
+    }
+    fn op24(&mut self, x: usize, y: usize, n: u8) {
+        self.emit(
+            format!("DRW V{:X}, V{:X}, {}", x, y, n),
+            format!("sprite v{:x} v{:x} {}", x, y, n),
+        );
+    }
+    fn op25(&mut self, x: usize) {
+        self.skip_if(format!("SKP V{:X}", x), format!("v{:x} -key", x));
+    }
+    fn op26(&mut self, x: usize) {
+        self.skip_if(format!("SKNP V{:X}", x), format!("v{:x} key", x));
+    }
+    fn op27(&mut self, x: usize) {
+        self.emit(format!("LD V{:X}, DT", x), format!("v{:x} := delay", x));
+    }
+    fn op28(&mut self, x: usize) {
+        self.emit(format!("LD V{:X}, K", x), format!("v{:x} := key", x));
+    }
+    fn op29(&mut self, x: usize) {
+        self.emit(format!("LD DT, V{:X}", x), format!("delay := v{:x}", x));
+    }
+    fn op30(&mut self, x: usize) {
+        self.emit(format!("LD ST, V{:X}", x), format!("buzzer := v{:x}", x));
+    }
+    fn op31(&mut self, x: usize) {
+        self.emit(format!("ADD I, V{:X}", x), format!("i += v{:x}", x));
+    }
+    fn op32(&mut self, x: usize) {
+        self.emit(format!("LD F, V{:X}", x), format!("i := hex v{:x}", x));
+    }
+    fn op33(&mut self, x: usize) {
+        self.emit(format!("LD B, V{:X}", x), format!("bcd v{:x}", x));
+    }
+    fn op34(&mut self, x: usize) {
+        self.emit(format!("LD [I], V{:X}", x), format!("save v{:x}", x));
+    }
+    fn op35(&mut self, x: usize) {
+        self.emit(format!("LD V{:X}, [I]", x), format!("load v{:x}", x));
+    }
+    fn op36(&mut self, n: u8) {
+        self.platform = Platform::SuperChip;
+        self.emit(format!("SCD {}", n), format!("scroll-down {}", n));
+    }
+    fn op37(&mut self, n: u8) {
+        self.platform = Platform::XoChip;
+        self.emit(format!("SCU {}", n), format!("scroll-up {}", n));
+    }
+    fn op38(&mut self) {
+        self.extension(Platform::SuperChip, "SCR", "scroll-right");
+    }
+    fn op39(&mut self) {
+        self.extension(Platform::SuperChip, "SCL", "scroll-left");
+    }
+    fn op40(&mut self) {
+        self.flow = Flow::Stop;
+        self.extension(Platform::SuperChip, "EXIT", "exit");
+    }
+    fn op41(&mut self) {
+        self.extension(Platform::SuperChip, "LOW", "lores");
+    }
+    fn op42(&mut self) {
+        self.extension(Platform::SuperChip, "HIGH", "hires");
+    }
+    fn op43(&mut self, x: usize, y: usize) {
+        self.platform = Platform::XoChip;
+        self.emit(
+            format!("SAVE V{:X} - V{:X}", x, y),
+            format!("save v{:x} - v{:x}", x, y),
+        );
+    }
+    fn op44(&mut self, x: usize, y: usize) {
+        self.platform = Platform::XoChip;
+        self.emit(
+            format!("LOAD V{:X} - V{:X}", x, y),
+            format!("load v{:x} - v{:x}", x, y),
+        );
+    }
+    fn op45(&mut self) {
+        self.platform = Platform::XoChip;
+        self.size = 4;
+        let nnnn = self.next_word as usize;
+        self.data_reference = Some(nnnn);
+        let target = self.address(nnnn);
+        self.emit(format!("LD I, {}", target), format!("i := long {}", target));
+    }
+    fn op46(&mut self, n: u8) {
+        self.platform = Platform::XoChip;
+        self.emit(format!("PLANE {}", n), format!("plane {}", n));
+    }
+    fn op47(&mut self) {
+        self.extension(Platform::XoChip, "AUDIO", "audio");
+    }
+    fn op48(&mut self, x: usize) {
+        self.platform = Platform::SuperChip;
+        self.emit(format!("LD HF, V{:X}", x), format!("i := bighex v{:x}", x));
+    }
+    fn op49(&mut self, x: usize) {
+        self.platform = Platform::XoChip;
+        self.emit(format!("PITCH V{:X}", x), format!("pitch := v{:x}", x));
+    }
+    fn op50(&mut self, x: usize) {
+        self.platform = Platform::SuperChip;
+        self.emit(format!("LD R, V{:X}", x), format!("saveflags v{:x}", x));
+    }
+    fn op51(&mut self, x: usize) {
+        self.platform = Platform::SuperChip;
+        self.emit(format!("LD V{:X}, R", x), format!("loadflags v{:x}", x));
+    }
+    fn unknown(&mut self, _opcode: u16) {
+        self.flow = Flow::Invalid;
+    }
+}
+
+/// A ROM loaded at `OFFSET_USABLE_MEM`, split into code and data.
+pub struct Disassembly<'a> {
+    rom: &'a [u8],
+    code: BTreeMap<usize, usize>, // address and size of every instruction
+    labels: BTreeMap<usize, String>,
+    platform: Platform,
+}
+
+impl<'a> Disassembly<'a> {
+    /// Walks every instruction reachable from the entry point,
+    /// following jumps, calls, skips and `BNNN` jump tables.
+    /// Everything never reached is considered as data.
+    pub fn new(rom: &'a [u8]) -> Disassembly<'a> {
+        let no_labels = BTreeMap::new();
+        let mut decoder = Decoder::new(Syntax::Classic, &no_labels);
+        let mut code = BTreeMap::new();
+        let mut platform = Platform::Chip8;
+        let mut kinds = BTreeMap::new();
+        let mut pending = vec![OFFSET_USABLE_MEM];
+
+        kinds.insert(OFFSET_USABLE_MEM, LabelKind::Main);
+
+        while let Some(address) = pending.pop() {
+            let opcode = match opcode_at(rom, address) {
+                Some(opcode) if !code.contains_key(&address) => opcode,
+                _ => continue,
+            };
+
+            decoder.decode(opcode, opcode_at(rom, address + 2).unwrap_or(0));
+
+            match decoder.flow {
+                Flow::Invalid => continue,
+                Flow::Next => pending.push(address + decoder.size),
+                Flow::Skip => {
+                    let next = address + decoder.size;
+                    pending.push(next);
+                    pending.push(next + instruction_size(rom, next));
+                }
+                Flow::Jump(target) => {
+                    add_label(&mut kinds, rom, target, LabelKind::Jump);
+                    pending.push(target);
+                }
+                Flow::Call(target) => {
+                    add_label(&mut kinds, rom, target, LabelKind::Subroutine);
+                    pending.push(target);
+                    pending.push(address + decoder.size);
+                }
+                Flow::JumpTable(base) => {
+                    add_label(&mut kinds, rom, base, LabelKind::Table);
+                    pending.push(base);
+                    // V0 is unknown here, so every consecutive jump of the table is an entry
+                    let mut entry = base;
+                    while let Some(0x1000..=0x1FFF) = opcode_at(rom, entry) {
+                        pending.push(entry);
+                        entry += 2;
+                    }
+                }
+                Flow::Return | Flow::Stop => {}
+            }
+
+            if let Some(target) = decoder.data_reference {
+                add_label(&mut kinds, rom, target, LabelKind::Data);
+            }
+
+            if decoder.platform > platform {
+                platform = decoder.platform;
+            }
+            code.insert(address, decoder.size);
+        }
+
+        let labels = kinds
+            .into_iter()
+            .map(|(address, kind)| {
+                let name = match kind {
+                    LabelKind::Main => "main".to_string(),
+                    LabelKind::Subroutine => format!("sub_{:03X}", address),
+                    LabelKind::Jump => format!("label_{:03X}", address),
+                    LabelKind::Table => format!("table_{:03X}", address),
+                    LabelKind::Data => format!("data_{:03X}", address),
+                };
+                (address, name)
+            })
+            .collect();
+
+        Disassembly {
+            rom,
+            code,
+            labels,
+            platform,
+        }
+    }
+
+    /// The first platform knowing every reachable instruction.
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    /// Number of bytes reached as instructions, the rest of the ROM being data.
+    pub fn code_size(&self) -> usize {
+        self.code.values().sum()
+    }
+
+    /// Renders the whole ROM, labels included, in the given syntax.
+    pub fn to_source(&self, syntax: Syntax) -> String {
+        let mut decoder = Decoder::new(syntax, &self.labels);
+        let mut source = String::new();
+        let mut data: Vec<u8> = Vec::new();
+        let end = OFFSET_USABLE_MEM + self.rom.len();
+        let mut address = OFFSET_USABLE_MEM;
+
+        while address < end {
+            let label = self.labels.get(&address);
+            // an instruction overlapped by a label is shown as data
+            let is_instruction = match self.code.get(&address) {
+                Some(&size) => {
+                    address + size <= end
+                        && self
+                            .labels
+                            .range(address + 1..address + size)
+                            .next()
+                            .is_none()
+                }
+                None => false,
+            };
+
+            if !data.is_empty() && (label.is_some() || is_instruction || data.len() == 8) {
+                self.write_data(&mut source, syntax, address - data.len(), &data);
+                data.clear();
+            }
+
+            if let Some(label) = label {
+                match syntax {
+                    Syntax::Classic => source.push_str(&format!("{}:\n", label)),
+                    Syntax::Octo => source.push_str(&format!(": {}\n", label)),
+                }
+            }
+
+            if is_instruction {
+                let opcode = opcode_at(self.rom, address).unwrap();
+                decoder.decode(opcode, opcode_at(self.rom, address + 2).unwrap_or(0));
+                match syntax {
+                    Syntax::Classic => source.push_str(&format!(
+                        "{:03X}: {:04X}  {}\n",
+                        address, opcode, decoder.text
+                    )),
+                    Syntax::Octo => source.push_str(&format!("  {}\n", decoder.text)),
+                }
+                address += decoder.size;
+            } else {
+                data.push(self.rom[address - OFFSET_USABLE_MEM]);
+                address += 1;
+            }
+        }
+
+        if !data.is_empty() {
+            self.write_data(&mut source, syntax, end - data.len(), &data);
+        }
+
+        source
+    }
+
+    fn write_data(&self, source: &mut String, syntax: Syntax, address: usize, data: &[u8]) {
+        let bytes: Vec<String> = data.iter().map(|byte| format!("0x{:02X}", byte)).collect();
+        match syntax {
+            Syntax::Classic => {
+                source.push_str(&format!("{:03X}: DB {}\n", address, bytes.join(", ")))
+            }
+            Syntax::Octo => source.push_str(&format!("  {}\n", bytes.join(" "))),
+        }
+    }
+}
+
+/// Classic mnemonic of a single opcode, `None` if it isn't a valid instruction.
+pub fn mnemonic(opcode: u16) -> Option<String> {
+    let no_labels = BTreeMap::new();
+    let mut decoder = Decoder::new(Syntax::Classic, &no_labels);
+    decoder.decode(opcode, 0);
+    match decoder.flow {
+        Flow::Invalid => None,
+        _ => Some(decoder.text),
+    }
+}
+
+/// Only addresses inside the ROM get a label, the others stay numeric.
+fn add_label(kinds: &mut BTreeMap<usize, LabelKind>, rom: &[u8], address: usize, kind: LabelKind) {
+    if address < OFFSET_USABLE_MEM || address >= OFFSET_USABLE_MEM + rom.len() {
+        return;
+    }
+    let current = kinds.entry(address).or_insert(kind);
+    if kind < *current {
+        *current = kind;
+    }
+}
+
+fn instruction_size(rom: &[u8], address: usize) -> usize {
+    match opcode_at(rom, address) {
+        Some(0xF000) => 4,
+        _ => 2,
+    }
+}
+
+fn opcode_at(rom: &[u8], address: usize) -> Option<u16> {
+    if address < OFFSET_USABLE_MEM || address + 1 >= OFFSET_USABLE_MEM + rom.len() {
+        return None;
+    }
+    let offset = address - OFFSET_USABLE_MEM;
+    Some(((rom[offset] as u16) << 8) + rom[offset + 1] as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::assembler::assemble;
+
+    /// Assembles the Octo disassembly of `rom` back into a ROM.
+    fn round_trip(rom: &[u8]) -> Vec<u8> {
+        let source = Disassembly::new(rom).to_source(Syntax::Octo);
+        match assemble(&source) {
+            Ok(program) => program.rom,
+            Err(error) => panic!("{}\n{}", error, source),
+        }
+    }
+
+    fn assembled(source: &str) -> Vec<u8> {
+        assemble(source).unwrap().rom
+    }
+
+    #[test]
+    fn assembly_round_trip() {
+        let rom = assembled(
+            ": main
+               clear
+               i := sprite
+               loop
+                 v1 := random 0x3F
+                 if v1 < 32 then v0 += 1
+                 if v0 != v1 begin
+                   sprite v0 v1 5
+                 else
+                   v2 := key
+                 end
+                 draw
+                 while v0 != 10
+               again
+               jump0 table
+             : draw
+               v3 <<= v4
+               bcd v3
+               save v2
+               load v0
+               delay := v3
+               ;
+             : table
+               jump main
+               jump draw
+             : sprite
+               0x20 0x60 0x20 0x20 0x70",
+        );
+        assert_eq!(round_trip(&rom), rom);
+    }
+
+    #[test]
+    fn extensions_round_trip() {
+        let rom = assembled(
+            ": main
+               hires
+               scroll-down 4
+               scroll-left
+               plane 3
+               i := long data
+               audio
+               save v1 - v4
+               load v2 - v3
+               saveflags v7
+               i := bighex v0
+               pitch := v5
+               exit
+             : data
+               1 2 3",
+        );
+        let disassembly = Disassembly::new(&rom);
+        assert_eq!(disassembly.platform(), Platform::XoChip);
+        assert_eq!(disassembly.code_size(), 26);
+        assert_eq!(round_trip(&rom), rom);
+    }
+
+    #[test]
+    fn data_and_unreachable_bytes() {
+        // the skipped jump reaches the odd address, which has to stay data
+        let rom = [0x12, 0x03, 0xFF, 0x00, 0xE0, 0x12, 0x05];
+        assert_eq!(round_trip(&rom), rom);
+        assert_eq!(Disassembly::new(&rom).code_size(), 6);
+        let rom = assembled(": main jump main : junk 0xAB 0xCD 0x0E");
+        assert_eq!(round_trip(&rom), rom);
+    }
+
+    #[test]
+    fn classic_mnemonics() {
+        assert_eq!(mnemonic(0x00E0).as_deref(), Some("CLS"));
+        assert_eq!(mnemonic(0x6A2F).as_deref(), Some("LD VA, 0x2F"));
+        assert_eq!(mnemonic(0xD125).as_deref(), Some("DRW V1, V2, 5"));
+        assert_eq!(mnemonic(0x00FF).as_deref(), Some("HIGH"));
+        assert_eq!(mnemonic(0x5121), None);
+        assert_eq!(mnemonic(0xE1FF), None);
+
+        let source =
+            Disassembly::new(&[0x22, 0x04, 0x12, 0x02, 0x00, 0xEE]).to_source(Syntax::Classic);
+        assert_eq!(
+            source,
+            "main:\n200: 2204  CALL sub_204\nlabel_202:\n202: 1202  JP label_202\nsub_204:\n204: 00EE  RET\n"
+        );
+    }
+}
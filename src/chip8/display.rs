@@ -0,0 +1,200 @@
+use super::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+pub const HIRES_WIDTH: u32 = SCREEN_WIDTH * 2;
+pub const HIRES_HEIGHT: u32 = SCREEN_HEIGHT * 2;
+
+/// The screen of the machine, 64x32 or 128x64 in hires mode.
+/// Every pixel holds one bit per plane: the first plane is the bit 0,
+/// the second plane (XO-CHIP only) is the bit 1.
+#[derive(Clone)]
+pub struct Display {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+    has_changed: bool,
+}
+
+impl Default for Display {
+    fn default() -> Display {
+        Display::new()
+    }
+}
+
+impl Display {
+    pub fn new() -> Display {
+        Display {
+            width: SCREEN_WIDTH as usize,
+            height: SCREEN_HEIGHT as usize,
+            pixels: vec![0; (SCREEN_WIDTH * SCREEN_HEIGHT) as usize],
+            has_changed: true,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Switches between 64x32 and 128x64, the screen is cleared in both cases.
+    pub fn set_hires(&mut self, hires: bool) {
+        let (width, height) = if hires {
+            (HIRES_WIDTH, HIRES_HEIGHT)
+        } else {
+            (SCREEN_WIDTH, SCREEN_HEIGHT)
+        };
+        self.width = width as usize;
+        self.height = height as usize;
+        self.pixels = vec![0; self.width * self.height];
+        self.has_changed = true;
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.width == HIRES_WIDTH as usize
+    }
+
+    /// The planes of every pixel, row by row.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Puts back pixels taken with `pixels`, fails if they don't fill the resolution.
+    pub fn restore(&mut self, hires: bool, pixels: &[u8]) -> Result<(), String> {
+        self.set_hires(hires);
+        if pixels.len() != self.pixels.len() {
+            return Err(format!(
+                "{} pixels don't fill a {}x{} display",
+                pixels.len(),
+                self.width,
+                self.height
+            ));
+        }
+        self.pixels.copy_from_slice(pixels);
+        Ok(())
+    }
+
+    /// The planes lit at (x, y).
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y * self.width + x]
+    }
+
+    /// Tells if the screen changed since the last call.
+    pub fn take_changes(&mut self) -> bool {
+        std::mem::replace(&mut self.has_changed, false)
+    }
+
+    pub fn clear(&mut self, planes: u8) {
+        for pixel in self.pixels.iter_mut() {
+            *pixel &= !planes;
+        }
+        self.has_changed = true;
+    }
+
+    /// XORs a sprite onto the selected planes, returns true if a lit pixel is turned off.
+    ///
+    /// The sprite is `rows` lines of 8 pixels, or 16 pixels if `wide`,
+    /// `sprite_bytes` holding one such sprite for each selected plane, in plane order.
+    /// The top left corner always wraps around the screen,
+    /// the rest of the sprite is either clipped or wrapped.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_sprite(
+        &mut self,
+        x: usize,
+        y: usize,
+        rows: usize,
+        wide: bool,
+        sprite_bytes: &[u8],
+        planes: u8,
+        clip: bool,
+    ) -> bool {
+        let bytes_per_row = if wide { 2 } else { 1 };
+        let (x, y) = (x % self.width, y % self.height);
+        let mut collision = false;
+        let mut sprite_offset = 0;
+
+        for plane in (0..2)
+            .map(|index| 1 << index)
+            .filter(|plane| planes & plane != 0)
+        {
+            for row in 0..rows {
+                let row_offset = sprite_offset + row * bytes_per_row;
+                let line = if wide {
+                    (sprite_bytes[row_offset] as u16) << 8 | sprite_bytes[row_offset + 1] as u16
+                } else {
+                    (sprite_bytes[row_offset] as u16) << 8
+                };
+
+                let mut py = y + row;
+                if py >= self.height {
+                    if clip {
+                        break;
+                    }
+                    py %= self.height;
+                }
+
+                for column in 0..bytes_per_row * 8 {
+                    if line & (0x8000 >> column) == 0 {
+                        continue;
+                    }
+
+                    let mut px = x + column;
+                    if px >= self.width {
+                        if clip {
+                            break;
+                        }
+                        px %= self.width;
+                    }
+
+                    let pixel = &mut self.pixels[py * self.width + px];
+                    if *pixel & plane != 0 {
+                        collision = true;
+                    }
+                    *pixel ^= plane;
+                }
+            }
+            sprite_offset += rows * bytes_per_row;
+        }
+
+        self.has_changed = true;
+        collision
+    }
+
+    pub fn scroll_down(&mut self, lines: usize, planes: u8) {
+        self.scroll(0, lines as isize, planes);
+    }
+
+    pub fn scroll_up(&mut self, lines: usize, planes: u8) {
+        self.scroll(0, -(lines as isize), planes);
+    }
+
+    pub fn scroll_right(&mut self, columns: usize, planes: u8) {
+        self.scroll(columns as isize, 0, planes);
+    }
+
+    pub fn scroll_left(&mut self, columns: usize, planes: u8) {
+        self.scroll(-(columns as isize), 0, planes);
+    }
+
+    /// Moves the selected planes, what leaves the screen is lost.
+    fn scroll(&mut self, dx: isize, dy: isize, planes: u8) {
+        let source = self.pixels.clone();
+        let (width, height) = (self.width as isize, self.height as isize);
+
+        for y in 0..height {
+            for x in 0..width {
+                let (sx, sy) = (x - dx, y - dy);
+                let moved = if sx >= 0 && sx < width && sy >= 0 && sy < height {
+                    source[(sy * width + sx) as usize] & planes
+                } else {
+                    0
+                };
+                let pixel = &mut self.pixels[(y * width + x) as usize];
+                *pixel = (*pixel & !planes) | moved;
+            }
+        }
+
+        self.has_changed = true;
+    }
+}
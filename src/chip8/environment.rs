@@ -0,0 +1,320 @@
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use super::display::Display;
+use super::headless_interface::HeadlessInterface;
+use super::Chip8;
+
+/// How a game shows its state in memory, to reward the agents and end the episodes.
+///
+/// ```toml
+/// frame_skip = 4
+/// # keypad keys held by each action, in hexadecimal, "" does nothing
+/// actions = ["", "4", "6"]
+/// # rewarded on each life lost
+/// life_loss = -1.0
+/// # the episodes end after this number of frames
+/// max_frames = 18000
+///
+/// # the reward is the increase of the score
+/// [score]
+/// address = 0x3F0
+/// encoding = "bcd"  # one decimal digit per byte, as written by FX33
+/// length = 3
+///
+/// # the episode ends when the lives fall to 0
+/// [lives]
+/// address = 0x3F4
+///
+/// # or when a byte of the memory takes a value
+/// [game_over]
+/// address = 0x3F5
+/// value = 1
+/// ```
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct GameSpec {
+    #[serde(default = "default_frame_skip")]
+    pub frame_skip: u32,
+    /// The actions, without it doing nothing and pressing each key alone.
+    pub actions: Option<Vec<String>>,
+    #[serde(default)]
+    pub life_loss: f64,
+    pub max_frames: Option<u64>,
+    pub score: Option<MemoryValue>,
+    pub lives: Option<MemoryValue>,
+    pub game_over: Option<MemoryCondition>,
+}
+
+fn default_frame_skip() -> u32 {
+    4
+}
+
+impl Default for GameSpec {
+    /// The frames, with the end of the program as the only end of the episodes.
+    fn default() -> GameSpec {
+        GameSpec {
+            frame_skip: default_frame_skip(),
+            actions: None,
+            life_loss: 0.,
+            max_frames: None,
+            score: None,
+            lives: None,
+            game_over: None,
+        }
+    }
+}
+
+impl FromStr for GameSpec {
+    type Err = String;
+
+    /// Reads the TOML of a game definition.
+    fn from_str(text: &str) -> Result<GameSpec, String> {
+        toml::from_str(text).map_err(|error| error.to_string().trim_end().to_string())
+    }
+}
+
+/// A number in memory.
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct MemoryValue {
+    pub address: usize,
+    #[serde(default)]
+    pub encoding: Encoding,
+    /// Bytes read [default: 1, or 3 for BCD]
+    pub length: Option<usize>,
+}
+
+#[derive(Deserialize, Clone, Copy, Default, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    /// Unsigned, big-endian like the words of the CHIP-8.
+    #[default]
+    Binary,
+    /// A decimal digit per byte, the most significant first.
+    Bcd,
+}
+
+impl MemoryValue {
+    pub fn read(&self, memory: &[u8]) -> u64 {
+        let length = self.length.unwrap_or(match self.encoding {
+            Encoding::Binary => 1,
+            Encoding::Bcd => 3,
+        });
+        let base = match self.encoding {
+            Encoding::Binary => 0x100,
+            Encoding::Bcd => 10,
+        };
+        (self.address..self.address + length)
+            .map(|address| memory[address % memory.len()] as u64)
+            .fold(0, |value, byte| value.wrapping_mul(base).wrapping_add(byte))
+    }
+}
+
+/// A byte of the memory having a value.
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct MemoryCondition {
+    pub address: usize,
+    pub value: u8,
+}
+
+/// A Gym-style environment: the agent picks an action, held for a few frames,
+/// and gets the display, the reward and whether the episode is over.
+pub struct Environment {
+    chip: Chip8<HeadlessInterface>,
+    rom: Vec<u8>,
+    spec: GameSpec,
+    /// The keys held by each action, a bit per key.
+    actions: Vec<u16>,
+    frame: u64,
+    score: u64,
+    lives: u64,
+    /// The lives are read as 0 until the game sets them.
+    has_lives: bool,
+    is_done: bool,
+}
+
+impl Environment {
+    /// The environment of a program, to `reset` before the first step.
+    pub fn new(rom: &[u8], spec: GameSpec) -> Result<Environment, String> {
+        let actions: Vec<u16> = match spec.actions {
+            Some(ref actions) => actions
+                .iter()
+                .map(|keys| parse_keys(keys))
+                .collect::<Result<_, _>>()?,
+            None => std::iter::once(0)
+                .chain((0..16).map(|key| 1 << key))
+                .collect(),
+        };
+        if actions.is_empty() {
+            return Err("the game has no actions".to_string());
+        }
+
+        let (chip, _) = Chip8::for_rom(rom)?;
+        Ok(Environment {
+            chip,
+            rom: rom.to_vec(),
+            spec,
+            actions,
+            frame: 0,
+            score: 0,
+            lives: 0,
+            has_lives: false,
+            is_done: true,
+        })
+    }
+
+    pub fn action_count(&self) -> usize {
+        self.actions.len()
+    }
+
+    pub fn chip(&self) -> &Chip8<HeadlessInterface> {
+        &self.chip
+    }
+
+    pub fn set_frame_skip(&mut self, frame_skip: u32) {
+        self.spec.frame_skip = frame_skip.max(1);
+    }
+
+    /// Starts the program again, with `seed` for reproducible episodes.
+    pub fn reset(&mut self, seed: Option<u64>) -> Result<&Display, String> {
+        let (chip, _) = Chip8::for_rom(&self.rom)?;
+        self.chip = chip;
+        if let Some(seed) = seed {
+            self.chip.set_seed(seed);
+        }
+        self.frame = 0;
+        self.score = self.read(self.spec.score);
+        self.lives = self.read(self.spec.lives);
+        self.has_lives = self.lives > 0;
+        self.is_done = false;
+        Ok(self.chip.display())
+    }
+
+    /// Holds the keys of `action` for `frame_skip` frames, less when the episode ends.
+    /// Gives the display, the reward and whether the episode is over.
+    pub fn step(&mut self, action: usize) -> Result<(&Display, f64, bool), String> {
+        let keys = *self.actions.get(action).ok_or_else(|| {
+            format!(
+                "no action {}, there are {} of them",
+                action,
+                self.actions.len()
+            )
+        })?;
+        if self.is_done {
+            return Err("the episode is over, it has to be reset".to_string());
+        }
+        for key in 0..16 {
+            self.chip.set_key(key, keys & (1 << key) != 0);
+        }
+
+        let mut reward = 0.;
+        for _ in 0..self.spec.frame_skip.max(1) {
+            self.chip.step_frame();
+            self.frame += 1;
+            reward += self.update();
+            if self.is_done {
+                break;
+            }
+        }
+        Ok((self.chip.display(), reward, self.is_done))
+    }
+
+    /// Reads the memory after a frame, and gives the reward of the frame.
+    fn update(&mut self) -> f64 {
+        let mut reward = 0.;
+
+        let score = self.read(self.spec.score);
+        reward += score as f64 - self.score as f64;
+        self.score = score;
+
+        let lives = self.read(self.spec.lives);
+        if lives < self.lives {
+            reward += self.spec.life_loss * (self.lives - lives) as f64;
+        }
+        self.lives = lives;
+        self.has_lives |= lives > 0;
+
+        let memory = self.chip.memory();
+        self.is_done = !self.chip.is_running()
+            || (self.has_lives && lives == 0)
+            || self.spec.game_over.is_some_and(|condition| {
+                memory[condition.address % memory.len()] == condition.value
+            })
+            || self.spec.max_frames.is_some_and(|max| self.frame >= max);
+        reward
+    }
+
+    fn read(&self, value: Option<MemoryValue>) -> u64 {
+        value.map_or(0, |value| value.read(self.chip.memory()))
+    }
+}
+
+/// `"4"` or `"46"`: the keys of an action in hexadecimal, nothing for no key.
+fn parse_keys(keys: &str) -> Result<u16, String> {
+    keys.chars().try_fold(0, |mask, key| {
+        key.to_digit(16)
+            .map(|key| mask | 1 << key)
+            .ok_or_else(|| format!("invalid keypad key '{}' in the action \"{}\"", key, keys))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_values() {
+        let memory = [1, 2, 3, 0x12, 0x34];
+        let value = |encoding, address, length| {
+            MemoryValue {
+                address,
+                encoding,
+                length,
+            }
+            .read(&memory)
+        };
+        assert_eq!(value(Encoding::Bcd, 0, None), 123);
+        assert_eq!(value(Encoding::Bcd, 1, Some(2)), 23);
+        assert_eq!(value(Encoding::Binary, 3, None), 0x12);
+        assert_eq!(value(Encoding::Binary, 3, Some(2)), 0x1234);
+        // past the end, like the CHIP-8 addresses
+        assert_eq!(value(Encoding::Binary, 4, Some(2)), 0x3401);
+    }
+
+    #[test]
+    fn life_loss() {
+        // stores 3 lives at 0x300, and loses one per frame
+        let rom = [
+            0x60, 0x03, // V0 = 3
+            0xA3, 0x00, // I = 0x300
+            0xF0, 0x55, // [I] = V0
+            0x61, 0x01, 0xF1, 0x15, // delay = 1
+            0xF1, 0x07, 0x31, 0x00, 0x12, 0x0A, // until the delay is over
+            0x70, 0xFF, // V0 -= 1
+            0x12, 0x02,
+        ];
+        let spec: GameSpec = "frame_skip = 1\nlife_loss = -1.0\n[lives]\naddress = 0x300\n"
+            .parse()
+            .unwrap();
+        let mut environment = Environment::new(&rom, spec).unwrap();
+        assert!(environment.step(0).is_err());
+        environment.reset(Some(1)).unwrap();
+
+        let mut rewards = Vec::new();
+        loop {
+            let (_, reward, is_done) = environment.step(0).unwrap();
+            rewards.push(reward);
+            if is_done {
+                break;
+            }
+        }
+        // no reward when the game sets the lives, then one loss per life
+        assert_eq!(rewards.iter().filter(|&&reward| reward != 0.).count(), 3);
+        assert_eq!(rewards.iter().sum::<f64>(), -3.);
+        assert_eq!(environment.chip().memory()[0x300], 0);
+        assert!(environment.step(0).is_err());
+    }
+}
@@ -0,0 +1,95 @@
+use std::str::FromStr;
+
+use super::graphic_engine::{EngineSettings, GraphicEngine};
+use super::headless_interface::HeadlessInterface;
+#[cfg(feature = "piston")]
+use super::piston_interface::PistonInterface;
+#[cfg(feature = "sdl")]
+use super::sdl_interface::SdlInterface;
+#[cfg(feature = "software")]
+use super::software_interface::SoftwareInterface;
+#[cfg(feature = "terminal")]
+use super::terminal_interface::TerminalInterface;
+
+/// A way to show the emulation, compiled in with the cargo feature of the same name.
+#[derive(Clone, Copy)]
+pub struct Frontend {
+    pub name: &'static str,
+    pub create: fn(EngineSettings) -> Result<Box<dyn GraphicEngine>, String>,
+}
+
+/// The frontends compiled in, tried in this order when none is chosen.
+const FRONTENDS: &[Frontend] = &[
+    #[cfg(feature = "piston")]
+    Frontend {
+        name: "piston",
+        create: |settings| Ok(Box::new(PistonInterface::new(settings)?)),
+    },
+    #[cfg(feature = "sdl")]
+    Frontend {
+        name: "sdl",
+        create: |settings| Ok(Box::new(SdlInterface::new(settings)?)),
+    },
+    // drawn by the CPU, without OpenGL
+    #[cfg(feature = "software")]
+    Frontend {
+        name: "software",
+        create: |settings| Ok(Box::new(SoftwareInterface::new(settings)?)),
+    },
+    #[cfg(feature = "terminal")]
+    Frontend {
+        name: "terminal",
+        create: |settings| Ok(Box::new(TerminalInterface::new(settings)?)),
+    },
+    // always there: no window, runs as fast as possible until the program stops
+    Frontend {
+        name: HEADLESS,
+        create: |settings| Ok(Box::new(HeadlessInterface::new(None, settings))),
+    },
+];
+
+pub const HEADLESS: &str = "headless";
+
+impl Frontend {
+    pub fn is_headless(&self) -> bool {
+        self.name == HEADLESS
+    }
+}
+
+/// Starts the first frontend that can, like the terminal when there is no display,
+/// the headless one only when it's the only one compiled in.
+/// The error tells why each one couldn't.
+pub fn first_available(settings: EngineSettings) -> Result<Box<dyn GraphicEngine>, String> {
+    let mut errors = Vec::new();
+    for frontend in FRONTENDS.iter().filter(|frontend| !frontend.is_headless()) {
+        match (frontend.create)(settings.clone()) {
+            Ok(engine) => return Ok(engine),
+            Err(error) => errors.push(format!("{}: {}", frontend.name, error)),
+        }
+    }
+    if errors.is_empty() {
+        return Ok(Box::new(HeadlessInterface::new(None, settings)));
+    }
+    errors.push("try --backend headless".to_string());
+    Err(format!("no backend could start; {}", errors.join("; ")))
+}
+
+impl FromStr for Frontend {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Frontend, String> {
+        let name = text.to_lowercase();
+        FRONTENDS
+            .iter()
+            .find(|frontend| frontend.name == name)
+            .copied()
+            .ok_or_else(|| {
+                let names: Vec<&str> = FRONTENDS.iter().map(|frontend| frontend.name).collect();
+                format!(
+                    "unknown or not compiled in backend '{}', try {}",
+                    text,
+                    names.join(", ")
+                )
+            })
+    }
+}
@@ -0,0 +1,202 @@
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use gif::{Encoder, Frame, Repeat};
+
+use super::display::Display;
+use super::graphic_engine::{EngineSettings, Palette};
+use super::screenshot;
+use super::FREQUENCY;
+
+/// Viewers show delays under 2 hundredths of a second as 10,
+/// shorter frames are replaced by the next one.
+const MIN_DELAY: u64 = 2;
+
+/// Records every frame into an animated GIF.
+///
+/// The pixels are written as their planes, indexes in a 4 colour palette.
+/// Identical frames are merged into a single longer one,
+/// so a mostly still game makes a small file.
+pub struct GifRecorder {
+    encoder: Encoder<BufWriter<File>>,
+    path: PathBuf,
+    scale: u32,
+    width: usize,
+    height: usize,
+    /// The frame not written yet, as its duration isn't known.
+    pending: Vec<u8>,
+    /// Frame at which the pending frame started to be shown.
+    pending_start: u64,
+    frame: u64,
+}
+
+impl GifRecorder {
+    /// The GIF is big enough for both resolutions at `scale`.
+    pub fn create(path: &Path, palette: &Palette, scale: u32) -> Result<GifRecorder, String> {
+        let error = |error: String| format!("cannot write '{}': {}", path.display(), error);
+
+        let (width, height) = screenshot::canvas_size(scale);
+
+        let file = File::create(path)
+            .map_err(|error| format!("cannot create '{}': {}", path.display(), error))?;
+        let colors: Vec<u8> = palette.colors.iter().flatten().copied().collect();
+        let mut encoder = Encoder::new(BufWriter::new(file), width as u16, height as u16, &colors)
+            .map_err(|error| error.to_string())
+            .map_err(error)?;
+        encoder
+            .set_repeat(Repeat::Infinite)
+            .map_err(|error| error.to_string())
+            .map_err(error)?;
+
+        Ok(GifRecorder {
+            encoder,
+            path: path.to_path_buf(),
+            scale,
+            width,
+            height,
+            pending: Vec::new(),
+            pending_start: 0,
+            frame: 0,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Adds the display shown during one frame.
+    pub fn add_frame(&mut self, display: &Display) -> Result<(), String> {
+        let pixels = screenshot::canvas(display, self.scale, self.width, self.height);
+
+        if self.frame == 0 {
+            self.pending = pixels;
+        } else if pixels != self.pending {
+            if self.delay(self.frame) >= MIN_DELAY {
+                self.write_pending()?;
+                self.pending_start = self.frame;
+            }
+            self.pending = pixels;
+        }
+
+        self.frame += 1;
+        Ok(())
+    }
+
+    /// Writes the last frame and the end of the file.
+    pub fn finish(mut self) -> Result<(), String> {
+        if self.frame > 0 {
+            self.write_pending()?;
+        }
+
+        let path = self.path;
+        self.encoder
+            .into_inner()
+            .and_then(|mut writer| writer.flush())
+            .map_err(|error| format!("cannot write '{}': {}", path.display(), error))
+    }
+
+    /// Duration in hundredths of a second of the pending frame if it ends at `end`,
+    /// rounded from the start of the recording so that the delays don't drift.
+    fn delay(&self, end: u64) -> u64 {
+        let hundredths = |frame: u64| (frame * 100 + FREQUENCY as u64 / 2) / FREQUENCY as u64;
+        hundredths(end) - hundredths(self.pending_start)
+    }
+
+    fn write_pending(&mut self) -> Result<(), String> {
+        let frame = Frame {
+            width: self.width as u16,
+            height: self.height as u16,
+            delay: self.delay(self.frame).min(u16::MAX as u64) as u16,
+            buffer: Cow::Borrowed(&self.pending),
+            ..Frame::default()
+        };
+
+        self.encoder
+            .write_frame(&frame)
+            .map_err(|error| format!("cannot write '{}': {}", self.path.display(), error))
+    }
+}
+
+/// The recording started and stopped with a hotkey.
+#[derive(Default)]
+pub struct Recording {
+    recorder: Option<GifRecorder>,
+}
+
+impl Recording {
+    /// Starts recording into `<name>-<n>.gif`, or stops the recording.
+    /// Tells the user what happened.
+    pub fn toggle(&mut self, settings: &EngineSettings) -> String {
+        match self.recorder.take() {
+            Some(recorder) => finish(recorder),
+            None => {
+                let path = screenshot::next_path(&settings.name, "gif");
+                match GifRecorder::create(&path, &settings.palette, settings.scale) {
+                    Ok(recorder) => {
+                        self.recorder = Some(recorder);
+                        format!("Recording into '{}'.", path.display())
+                    }
+                    Err(error) => format!("error: {}", error),
+                }
+            }
+        }
+    }
+
+    /// Tells the user how the recording ended, if there was one.
+    pub fn stop(&mut self) -> Option<String> {
+        self.recorder.take().map(finish)
+    }
+
+    /// Ends the recording if there is one, and gives its file.
+    pub fn finish(&mut self) -> Result<Option<PathBuf>, String> {
+        match self.recorder.take() {
+            Some(recorder) => {
+                let path = recorder.path().to_path_buf();
+                recorder.finish().map(|()| Some(path))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// Adds the display shown during one frame, the recording stops on errors.
+    pub fn add_frame(&mut self, display: &Display) -> Result<(), String> {
+        if let Some(ref mut recorder) = self.recorder {
+            if let Err(error) = recorder.add_frame(display) {
+                self.recorder = None;
+                return Err(error);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl From<GifRecorder> for Recording {
+    /// A recording already started, from the command line.
+    fn from(recorder: GifRecorder) -> Recording {
+        Recording {
+            recorder: Some(recorder),
+        }
+    }
+}
+
+impl Drop for Recording {
+    fn drop(&mut self) {
+        if let Some(message) = self.stop() {
+            println!("{}", message);
+        }
+    }
+}
+
+fn finish(recorder: GifRecorder) -> String {
+    let path = recorder.path().to_path_buf();
+    match recorder.finish() {
+        Ok(()) => format!("Saved the recording '{}'.", path.display()),
+        Err(error) => format!("error: {}", error),
+    }
+}
@@ -1,11 +1,321 @@
+use std::str::FromStr;
+
+use super::display::Display;
+use super::persistence::Persistence;
+use super::viewport::Scaling;
+
 pub trait GraphicEngine {
-    fn clear_screen(&mut self);
-    /// Draw a sprite from bytes.
-    /// One byte is a line of 8 pixels, one pixel for each bit.
-    /// If the bit equals one the pixel is on, off otherwise.
-    ///
-    fn draw_sprite(&mut self, x: u8, y: u8, sprite_bytes: &[u8]) -> bool;
-    fn flush(&mut self);
+    /// Shows the display.
+    /// Called at the end of every frame where the display changed.
+    fn draw(&mut self, display: &Display);
+    /// Handles the pending events and updates the state of the 16 keys.
+    /// Called once per frame.
+    fn flush(&mut self, keypad: &mut [bool; 16]);
     fn is_running(&self) -> bool;
     fn init_draw(&mut self);
+    /// A headless engine runs the emulation as fast as possible
+    /// and stops as soon as the program does.
+    fn is_headless(&self) -> bool {
+        false
+    }
+    /// Writes what is left once the emulation is over, like the end of a recording.
+    fn finish(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// What the user can tune in every engine.
+#[derive(Clone)]
+pub struct EngineSettings {
+    /// Shown in the window title.
+    pub title: String,
+    /// The ROM file name without extension, names the files written with the hotkeys.
+    pub name: String,
+    /// Size of a low resolution pixel, in screen pixels, when the window opens.
+    pub scale: u32,
+    /// How the display fills a resized window.
+    pub scaling: Scaling,
+    pub palette: Palette,
+    pub keymap: Keymap,
+    /// Against the flicker, `None` shows every frame as it is.
+    pub persistence: Option<Persistence>,
+    pub renderer: Renderer,
+}
+
+impl Default for EngineSettings {
+    fn default() -> EngineSettings {
+        EngineSettings {
+            title: "chip8".to_string(),
+            name: "chip8".to_string(),
+            scale: 4,
+            scaling: Scaling::Integer,
+            palette: Palette::default(),
+            keymap: Keymap::default(),
+            persistence: None,
+            renderer: Renderer::Accelerated,
+        }
+    }
+}
+
+/// What draws the window.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Renderer {
+    /// The GPU, in sync with the refresh of the screen.
+    Accelerated,
+    /// The CPU, for the machines without GPU drivers.
+    Software,
+}
+
+impl FromStr for Renderer {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Renderer, String> {
+        match text.to_lowercase().as_str() {
+            "accelerated" | "gpu" => Ok(Renderer::Accelerated),
+            "software" | "cpu" => Ok(Renderer::Software),
+            _ => Err(format!(
+                "unknown renderer '{}', try accelerated or software",
+                text
+            )),
+        }
+    }
+}
+
+/// RGB colours of the background, the first plane, the second plane and both planes.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Palette {
+    pub colors: [[u8; 3]; 4],
+}
+
+/// The palettes known by name, in the order of the palette hotkey.
+const PALETTES: [(&str, Palette); 6] = [
+    (
+        "classic",
+        Palette {
+            colors: [
+                [0x00, 0x00, 0x00],
+                [0xFF, 0xFF, 0xFF],
+                [0xAA, 0xAA, 0xAA],
+                [0x55, 0x55, 0x55],
+            ],
+        },
+    ),
+    (
+        "amber",
+        Palette {
+            colors: [
+                [0x1A, 0x10, 0x00],
+                [0xFF, 0xB0, 0x00],
+                [0x99, 0x66, 0x00],
+                [0xFF, 0xDD, 0x88],
+            ],
+        },
+    ),
+    (
+        "green-phosphor",
+        Palette {
+            colors: [
+                [0x00, 0x14, 0x00],
+                [0x33, 0xFF, 0x33],
+                [0x1A, 0x99, 0x1A],
+                [0xAA, 0xFF, 0xAA],
+            ],
+        },
+    ),
+    (
+        "lcd",
+        Palette {
+            colors: [
+                [0x9B, 0xBC, 0x0F],
+                [0x0F, 0x38, 0x0F],
+                [0x8B, 0xAC, 0x0F],
+                [0x30, 0x62, 0x30],
+            ],
+        },
+    ),
+    (
+        "high-contrast",
+        Palette {
+            colors: [
+                [0x00, 0x00, 0x00],
+                [0xFF, 0xFF, 0xFF],
+                [0xFF, 0xFF, 0x00],
+                [0x00, 0xFF, 0xFF],
+            ],
+        },
+    ),
+    // Okabe-Ito colours, told apart with any colour vision deficiency
+    (
+        "color-blind",
+        Palette {
+            colors: [
+                [0x00, 0x00, 0x00],
+                [0xF0, 0xE4, 0x42],
+                [0x00, 0x72, 0xB2],
+                [0xD5, 0x5E, 0x00],
+            ],
+        },
+    ),
+];
+
+impl Default for Palette {
+    fn default() -> Palette {
+        PALETTES[0].1
+    }
+}
+
+impl Palette {
+    /// The name of the palette if it's a known one.
+    pub fn name(&self) -> Option<&'static str> {
+        PALETTES
+            .iter()
+            .find(|(_, palette)| palette == self)
+            .map(|(name, _)| *name)
+    }
+
+    /// The known palette after this one, the first one after a custom palette.
+    pub fn next(&self) -> Palette {
+        let index = PALETTES
+            .iter()
+            .position(|(_, palette)| palette == self)
+            .map_or(0, |index| (index + 1) % PALETTES.len());
+        PALETTES[index].1
+    }
+
+    /// The name of the palette, or its colours if it's a custom one.
+    pub fn description(&self) -> String {
+        match self.name() {
+            Some(name) => name.to_string(),
+            None => self
+                .colors
+                .iter()
+                .map(|[r, g, b]| format!("{:02X}{:02X}{:02X}", r, g, b))
+                .collect::<Vec<String>>()
+                .join(","),
+        }
+    }
+
+    /// Colour of a pixel holding the given planes.
+    pub fn color(&self, planes: u8) -> [u8; 3] {
+        self.colors[(planes & 0b11) as usize]
+    }
+}
+
+impl FromStr for Palette {
+    type Err = String;
+
+    /// A palette name like `amber`, or two or four comma separated hexadecimal colours
+    /// like `000000,FFFFFF`. With only two colours, the second one is used for every plane.
+    fn from_str(text: &str) -> Result<Palette, String> {
+        let name = text.trim().to_lowercase().replace(['_', ' '], "-");
+        let name = match name.as_str() {
+            "green" => "green-phosphor",
+            "colour-blind" | "colorblind" | "colourblind" => "color-blind",
+            name => name,
+        };
+        if let Some((_, palette)) = PALETTES.iter().find(|(known, _)| *known == name) {
+            return Ok(*palette);
+        }
+        if !text.contains(',') {
+            let names: Vec<&str> = PALETTES.iter().map(|(name, _)| *name).collect();
+            return Err(format!(
+                "unknown palette '{}', try {} or colours like 000000,FFFFFF",
+                text,
+                names.join(", ")
+            ));
+        }
+
+        let colors = text
+            .split(',')
+            .map(|color| {
+                let hex = color.trim().trim_start_matches('#');
+                match u32::from_str_radix(hex, 16) {
+                    Ok(rgb) if hex.len() == 6 => {
+                        Ok([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8])
+                    }
+                    _ => Err(format!("'{}' isn't a RRGGBB colour", color)),
+                }
+            })
+            .collect::<Result<Vec<[u8; 3]>, String>>()?;
+
+        match colors.len() {
+            2 => Ok(Palette {
+                colors: [colors[0], colors[1], colors[1], colors[1]],
+            }),
+            4 => Ok(Palette {
+                colors: [colors[0], colors[1], colors[2], colors[3]],
+            }),
+            _ => Err("a palette has 2 or 4 colours".to_string()),
+        }
+    }
+}
+
+/// The keyboard key for each of the 16 keys of the keypad.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Keymap {
+    keys: [char; 16],
+}
+
+impl Default for Keymap {
+    /// The layout of the COSMAC VIP keypad on the left of a QWERTY keyboard.
+    ///
+    /// ```text
+    /// 1 2 3 C      1 2 3 4
+    /// 4 5 6 D      Q W E R
+    /// 7 8 9 E  ->  A S D F
+    /// A 0 B F      Z X C V
+    /// ```
+    fn default() -> Keymap {
+        "x123qweasdzc4rfv".parse().unwrap()
+    }
+}
+
+impl Keymap {
+    /// The keypad key bound to a keyboard key.
+    pub fn key(&self, keyboard_key: char) -> Option<u8> {
+        let keyboard_key = keyboard_key.to_ascii_lowercase();
+        self.keys
+            .iter()
+            .position(|key| *key == keyboard_key)
+            .map(|key| key as u8)
+    }
+
+    /// Binds a keyboard key to a keypad key,
+    /// the keyboard key previously bound to the keypad key takes its old place.
+    pub fn bind(&mut self, keyboard_key: char, key: u8) {
+        let keyboard_key = keyboard_key.to_ascii_lowercase();
+        let key = (key & 0xF) as usize;
+        if let Some(old_key) = self.keys.iter().position(|c| *c == keyboard_key) {
+            self.keys.swap(old_key, key);
+        } else {
+            self.keys[key] = keyboard_key;
+        }
+    }
+}
+
+impl FromStr for Keymap {
+    type Err = String;
+
+    /// 16 different characters, the keyboard keys of the keypad keys 0 to F.
+    fn from_str(text: &str) -> Result<Keymap, String> {
+        let chars: Vec<char> = text.chars().map(|c| c.to_ascii_lowercase()).collect();
+
+        if chars.len() != 16 {
+            return Err(format!(
+                "a keymap has 16 keys (one for each of 0 to F), '{}' has {}",
+                text,
+                chars.len()
+            ));
+        }
+
+        let mut keys = [' '; 16];
+        for (index, c) in chars.into_iter().enumerate() {
+            if keys[..index].contains(&c) {
+                return Err(format!("the key '{}' is used twice", c));
+            }
+            keys[index] = c;
+        }
+
+        Ok(Keymap { keys })
+    }
 }
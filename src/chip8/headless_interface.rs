@@ -0,0 +1,163 @@
+use std::path::PathBuf;
+
+use super::display::Display;
+use super::gif_recorder::{GifRecorder, Recording};
+use super::graphic_engine::{EngineSettings, GraphicEngine};
+use super::screenshot;
+use super::video_recorder::VideoRecorder;
+
+/// Runs the emulation without any window, for traces and tests.
+pub struct HeadlessInterface {
+    remaining_frames: Option<u64>,
+    settings: EngineSettings,
+    display: Display,
+    frame: u64,
+    /// The frame after which the screenshot is written, and where.
+    screenshot: Option<(u64, PathBuf)>,
+    recording: Recording,
+    video: Option<VideoRecorder>,
+    /// The first file that couldn't be written, returned by `finish`.
+    error: Option<String>,
+    is_finished: bool,
+}
+
+impl HeadlessInterface {
+    /// Stops after `frames` frames, or only when the program stops if `None`.
+    pub fn new(frames: Option<u64>, settings: EngineSettings) -> HeadlessInterface {
+        HeadlessInterface {
+            remaining_frames: frames,
+            settings,
+            display: Display::new(),
+            frame: 0,
+            screenshot: None,
+            recording: Recording::default(),
+            video: None,
+            error: None,
+            is_finished: false,
+        }
+    }
+
+    /// Writes the display to `path` once `frame` frames are done,
+    /// or when the emulation stops if it's before.
+    pub fn set_screenshot(&mut self, frame: u64, path: PathBuf) {
+        self.screenshot = Some((frame, path));
+    }
+
+    /// Records every frame until the emulation stops.
+    pub fn set_recording(&mut self, recorder: GifRecorder) {
+        self.recording = Recording::from(recorder);
+    }
+
+    /// Writes every frame into a video until the emulation stops.
+    pub fn set_video(&mut self, recorder: VideoRecorder) {
+        self.video = Some(recorder);
+    }
+
+    /// Messages go to stderr when the video is written on stdout.
+    fn report(&self, message: String) {
+        match self.video {
+            Some(ref video) if video.is_stdout() => eprintln!("{}", message),
+            _ => println!("{}", message),
+        }
+    }
+
+    /// Keeps the first error for `finish`, the run goes on without the file.
+    fn fail(&mut self, error: String) {
+        self.error.get_or_insert(error);
+    }
+
+    /// Records the display shown during the last frame.
+    fn record(&mut self) {
+        if self.frame == 0 {
+            return;
+        }
+
+        if let Err(error) = self.recording.add_frame(&self.display) {
+            self.fail(error);
+        }
+        if let Some(ref mut video) = self.video {
+            if let Err(error) = video.add_frame(&self.display) {
+                self.video = None;
+                self.fail(error);
+            }
+        }
+    }
+
+    fn take_screenshot(&mut self) {
+        if let Some((_, path)) = self.screenshot.take() {
+            match screenshot::save(
+                &self.display,
+                &self.settings.palette,
+                self.settings.scale,
+                &path,
+            ) {
+                Ok(()) => self.report(format!("Saved the screenshot '{}'.", path.display())),
+                Err(error) => self.fail(error),
+            }
+        }
+    }
+}
+
+impl GraphicEngine for HeadlessInterface {
+    fn draw(&mut self, display: &Display) {
+        self.display = display.clone();
+    }
+
+    fn flush(&mut self, _keypad: &mut [bool; 16]) {
+        // called at the start of a frame, all the previous ones are done
+        self.record();
+        if let Some((frame, _)) = self.screenshot {
+            if frame == self.frame {
+                self.take_screenshot();
+            }
+        }
+        self.frame += 1;
+
+        if let Some(ref mut frames) = self.remaining_frames {
+            *frames = frames.saturating_sub(1);
+        }
+    }
+
+    fn is_running(&self) -> bool {
+        self.remaining_frames != Some(0)
+    }
+
+    fn init_draw(&mut self) {}
+
+    fn is_headless(&self) -> bool {
+        true
+    }
+
+    /// The last frame isn't followed by a call to `flush`,
+    /// and the emulation can stop before the frame of the screenshot starts.
+    fn finish(&mut self) -> Result<(), String> {
+        if self.is_finished {
+            return Ok(());
+        }
+        self.is_finished = true;
+
+        self.record();
+        self.take_screenshot();
+
+        match self.recording.finish() {
+            Ok(Some(path)) => self.report(format!("Saved the recording '{}'.", path.display())),
+            Ok(None) => {}
+            Err(error) => self.fail(error),
+        }
+        if let Some(video) = self.video.take() {
+            if let Err(error) = video.finish() {
+                self.fail(error);
+            }
+        }
+        self.error.take().map_or(Ok(()), Err)
+    }
+}
+
+impl Drop for HeadlessInterface {
+    /// When `finish` wasn't called, like after a panic.
+    fn drop(&mut self) {
+        if let Err(error) = self.finish() {
+            eprintln!("error: {}", error);
+        }
+    }
+}
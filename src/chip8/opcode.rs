@@ -14,12 +14,29 @@ pub trait OpCode {
             self.op3();
         } else {
             match last_hex {
-                0x0 => self.op1(),
+                0x0 => match opcode & 0x0FF0 {
+                    0x00C0 => self.op36(n),
+                    0x00D0 => self.op37(n),
+                    0x00F0 => match n {
+                        0xB => self.op38(),
+                        0xC => self.op39(),
+                        0xD => self.op40(),
+                        0xE => self.op41(),
+                        0xF => self.op42(),
+                        _ => self.op1(),
+                    },
+                    _ => self.op1(),
+                },
                 0x1 => self.op4(nnn),
                 0x2 => self.op5(nnn),
                 0x3 => self.op6(x, nn),
                 0x4 => self.op7(x, nn),
-                0x5 => self.op8(x, y),
+                0x5 => match n {
+                    0x0 => self.op8(x, y),
+                    0x2 => self.op43(x, y),
+                    0x3 => self.op44(x, y),
+                    _ => self.unknown(opcode),
+                },
                 0x6 => self.op9(x, nn),
                 0x7 => self.op10(x, nn),
                 0x8 => match opcode & 0x000F {
@@ -29,12 +46,15 @@ pub trait OpCode {
                     0x3 => self.op14(x, y),
                     0x4 => self.op15(x, y),
                     0x5 => self.op16(x, y),
-                    0x6 => self.op17(x),
+                    0x6 => self.op17(x, y),
                     0x7 => self.op18(x, y),
-                    0xE => self.op19(x),
-                    _ => panic!("Unknown opcode provided! {:X?}", opcode),
+                    0xE => self.op19(x, y),
+                    _ => self.unknown(opcode),
+                },
+                0x9 => match n {
+                    0x0 => self.op20(x, y),
+                    _ => self.unknown(opcode),
                 },
-                0x9 => self.op20(x, y),
                 0xA => self.op21(nnn),
                 0xB => self.op22(nnn),
                 0xC => self.op23(x, nn),
@@ -42,9 +62,12 @@ pub trait OpCode {
                 0xE => match opcode & 0x00FF {
                     0x9E => self.op25(x),
                     0xA1 => self.op26(x),
-                    _ => panic!("Unknown opcode provided! {:X?}", opcode),
+                    _ => self.unknown(opcode),
                 },
                 0xF => match opcode & 0x00FF {
+                    0x00 if x == 0 => self.op45(),
+                    0x01 => self.op46(x as u8),
+                    0x02 if x == 0 => self.op47(),
                     0x07 => self.op27(x),
                     0x0A => self.op28(x),
                     0x15 => self.op29(x),
@@ -54,9 +77,13 @@ pub trait OpCode {
                     0x33 => self.op33(x),
                     0x55 => self.op34(x),
                     0x65 => self.op35(x),
-                    _ => panic!("Unknown opcode provided! {:X?}", opcode),
+                    0x30 => self.op48(x),
+                    0x3A => self.op49(x),
+                    0x75 => self.op50(x),
+                    0x85 => self.op51(x),
+                    _ => self.unknown(opcode),
                 },
-                _ => panic!("Unknown opcode provided! {:X?}", opcode),
+                _ => self.unknown(opcode),
             }
         }
     }
@@ -162,12 +189,13 @@ pub trait OpCode {
     ///
     /// * `opcode` - Opcode 8XY5
     fn op16(&mut self, x: usize, y: usize);
-    /// Stores the least significant bit of VX in VF and then shifts VX to the right by 1.
+    /// Stores the least significant bit of VY in VF and then shifts VY to the right by 1
+    /// into VX (the shifting quirk shifts VX instead).
     ///
     /// # Arguments
     ///
     /// * `opcode` - Opcode 8XY6
-    fn op17(&mut self, x: usize);
+    fn op17(&mut self, x: usize, y: usize);
     /// Sets VX to VY minus VX.
     /// VF is set to 0 when there's a borrow, and 1 when there isn't.
     ///
@@ -175,12 +203,13 @@ pub trait OpCode {
     ///
     /// * `opcode` - Opcode 8XY7
     fn op18(&mut self, x: usize, y: usize);
-    /// Stores the most significant bit of VX in VF and then shifts VX to the left by 1.
+    /// Stores the most significant bit of VY in VF and then shifts VY to the left by 1
+    /// into VX (the shifting quirk shifts VX instead).
     ///
     /// # Arguments
     ///
     /// * `opcode` - Opcode 8XYE
-    fn op19(&mut self, x: usize);
+    fn op19(&mut self, x: usize, y: usize);
     /// Skips the next instruction if VX doesn't equal VY
     /// (usually the next instruction is a jump to skip a code block).
     ///
@@ -297,4 +326,109 @@ pub trait OpCode {
     ///
     /// * `opcode` - Opcode FX65
     fn op35(&mut self, x: usize);
+    /// Scrolls the display down by N pixels (SuperChip).
+    ///
+    /// # Arguments
+    ///
+    /// * `opcode` - Opcode 00CN
+    fn op36(&mut self, n: u8);
+    /// Scrolls the display up by N pixels (XO-CHIP).
+    ///
+    /// # Arguments
+    ///
+    /// * `opcode` - Opcode 00DN
+    fn op37(&mut self, n: u8);
+    /// Scrolls the display right by 4 pixels (SuperChip).
+    ///
+    /// # Arguments
+    ///
+    /// * `opcode` - Opcode 00FB
+    fn op38(&mut self);
+    /// Scrolls the display left by 4 pixels (SuperChip).
+    ///
+    /// # Arguments
+    ///
+    /// * `opcode` - Opcode 00FC
+    fn op39(&mut self);
+    /// Exits the interpreter (SuperChip).
+    ///
+    /// # Arguments
+    ///
+    /// * `opcode` - Opcode 00FD
+    fn op40(&mut self);
+    /// Switches to the 64x32 low resolution (SuperChip).
+    ///
+    /// # Arguments
+    ///
+    /// * `opcode` - Opcode 00FE
+    fn op41(&mut self);
+    /// Switches to the 128x64 high resolution (SuperChip).
+    ///
+    /// # Arguments
+    ///
+    /// * `opcode` - Opcode 00FF
+    fn op42(&mut self);
+    /// Stores VX to VY (VY can be lower than VX) in memory starting at address I,
+    /// I is not modified (XO-CHIP).
+    ///
+    /// # Arguments
+    ///
+    /// * `opcode` - Opcode 5XY2
+    fn op43(&mut self, x: usize, y: usize);
+    /// Fills VX to VY (VY can be lower than VX) with values from memory starting at address I,
+    /// I is not modified (XO-CHIP).
+    ///
+    /// # Arguments
+    ///
+    /// * `opcode` - Opcode 5XY3
+    fn op44(&mut self, x: usize, y: usize);
+    /// Sets I to the 16 bits address NNNN stored in the next two bytes (XO-CHIP).
+    ///
+    /// # Arguments
+    ///
+    /// * `opcode` - Opcode F000 NNNN
+    fn op45(&mut self);
+    /// Selects the planes N (a bitmask) drawn and cleared by the next instructions (XO-CHIP).
+    ///
+    /// # Arguments
+    ///
+    /// * `opcode` - Opcode FN01
+    fn op46(&mut self, n: u8);
+    /// Loads the 16 bytes audio pattern from memory starting at address I (XO-CHIP).
+    ///
+    /// # Arguments
+    ///
+    /// * `opcode` - Opcode F002
+    fn op47(&mut self);
+    /// Sets I to the location of the sprite for the character in VX.
+    /// Characters 0-9 are represented by a 8x10 font (SuperChip).
+    ///
+    /// # Arguments
+    ///
+    /// * `opcode` - Opcode FX30
+    fn op48(&mut self, x: usize);
+    /// Sets the pitch of the audio pattern to VX (XO-CHIP).
+    ///
+    /// # Arguments
+    ///
+    /// * `opcode` - Opcode FX3A
+    fn op49(&mut self, x: usize);
+    /// Stores V0 to VX (including VX) in the persistent flags (SuperChip).
+    ///
+    /// # Arguments
+    ///
+    /// * `opcode` - Opcode FX75
+    fn op50(&mut self, x: usize);
+    /// Fills V0 to VX (including VX) with values from the persistent flags (SuperChip).
+    ///
+    /// # Arguments
+    ///
+    /// * `opcode` - Opcode FX85
+    fn op51(&mut self, x: usize);
+    /// Called when the opcode doesn't match any known instruction.
+    ///
+    /// # Arguments
+    ///
+    /// * `opcode` - The unknown opcode
+    fn unknown(&mut self, opcode: u16);
 }
@@ -0,0 +1,127 @@
+use std::str::FromStr;
+
+use super::display::Display;
+use super::graphic_engine::Palette;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PersistenceMode {
+    /// Every pixel moves toward its new colour, averaging the last frames.
+    Blend,
+    /// Pixels light up at once and fade out slowly, like on a CRT.
+    Phosphor,
+}
+
+/// Hides the flicker of the sprites erased and drawn again with XOR.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Persistence {
+    pub mode: PersistenceMode,
+    /// Part of the previous colour kept at every frame, from 0 (none) to 1 (forever).
+    pub strength: f32,
+}
+
+impl FromStr for Persistence {
+    type Err = String;
+
+    /// `blend` or `phosphor`, optionally followed by the strength: `phosphor:0.7`.
+    fn from_str(text: &str) -> Result<Persistence, String> {
+        let mut parts = text.splitn(2, ':');
+        let mode = match parts.next().unwrap_or("").trim().to_lowercase().as_str() {
+            "blend" => PersistenceMode::Blend,
+            "phosphor" => PersistenceMode::Phosphor,
+            mode => {
+                return Err(format!(
+                    "unknown persistence '{}', try blend or phosphor",
+                    mode
+                ))
+            }
+        };
+        let strength = match parts.next() {
+            Some(strength) => match strength.trim().parse::<f32>() {
+                Ok(strength) if (0. ..1.).contains(&strength) => strength,
+                _ => {
+                    return Err(format!(
+                        "the persistence strength '{}' isn't a number from 0 to 1 excluded",
+                        strength
+                    ))
+                }
+            },
+            None => 0.5,
+        };
+
+        Ok(Persistence { mode, strength })
+    }
+}
+
+/// The colours shown on the screen, following the display frame after frame.
+pub struct Afterglow {
+    persistence: Persistence,
+    width: usize,
+    height: usize,
+    colors: Vec<[f32; 3]>,
+}
+
+impl Afterglow {
+    pub fn new(persistence: Persistence) -> Afterglow {
+        Afterglow {
+            persistence,
+            width: 0,
+            height: 0,
+            colors: Vec::new(),
+        }
+    }
+
+    /// Moves the colours one frame toward the display.
+    /// Tells if the screen has to be drawn again.
+    pub fn update(&mut self, display: &Display, palette: &Palette) -> bool {
+        // the resolution changed, nothing is kept from the other one
+        if (self.width, self.height) != (display.width(), display.height()) {
+            self.width = display.width();
+            self.height = display.height();
+            self.colors = vec![to_f32(palette.color(0)); self.width * self.height];
+        }
+
+        let strength = self.persistence.strength;
+        let mut has_changed = false;
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let planes = display.pixel(x, y);
+                let target = to_f32(palette.color(planes));
+                let color = &mut self.colors[y * self.width + x];
+                let previous = *color;
+
+                for channel in 0..3 {
+                    color[channel] = match self.persistence.mode {
+                        PersistenceMode::Phosphor if planes != 0 => target[channel],
+                        _ => target[channel] + (previous[channel] - target[channel]) * strength,
+                    };
+                    // close enough, the fading is over
+                    if (color[channel] - target[channel]).abs() < 0.5 {
+                        color[channel] = target[channel];
+                    }
+                }
+
+                has_changed |= to_u8(*color) != to_u8(previous);
+            }
+        }
+
+        has_changed
+    }
+
+    /// Colour shown at (x, y).
+    pub fn color(&self, x: usize, y: usize) -> [u8; 3] {
+        to_u8(self.colors[y * self.width + x])
+    }
+}
+
+fn to_f32(rgb: [u8; 3]) -> [f32; 3] {
+    [rgb[0] as f32, rgb[1] as f32, rgb[2] as f32]
+}
+
+fn to_u8(rgb: [f32; 3]) -> [u8; 3] {
+    [
+        rgb[0].round() as u8,
+        rgb[1].round() as u8,
+        rgb[2].round() as u8,
+    ]
+}
@@ -1,130 +1,215 @@
-use std::{collections::VecDeque, sync::Arc, sync::Mutex, thread};
+use std::{panic, sync::mpsc, sync::Arc, sync::Mutex, thread};
 
 use glutin_window::GlutinWindow;
-use graphics::{clear, rectangle, types::Rectangle};
-use opengl_graphics::{GlGraphics, OpenGL};
+use graphics::{clear, Image, ImageSize};
+use opengl_graphics::{
+    CreateTexture, Filter, Format, GlGraphics, OpenGL, Texture, TextureSettings, UpdateTexture,
+};
 use piston::event_loop::{EventSettings, Events};
-use piston::input::RenderEvent;
+use piston::input::{Button, Key, PressEvent, ReleaseEvent, RenderEvent};
 use piston::window::WindowSettings;
 
-use super::graphic_engine::GraphicEngine;
+use super::display::Display;
+use super::gif_recorder::Recording;
+use super::graphic_engine::{EngineSettings, GraphicEngine};
+use super::persistence::Afterglow;
+use super::screenshot;
+use super::viewport::Viewport;
 
 pub struct PistonInterface {
     is_running: Arc<Mutex<bool>>,
-    task_data_queue: Arc<Mutex<VecDeque<TaskData>>>,
+    /// The frame shown by the window, swapped with `frame` when a new one is ready.
+    shared_frame: Arc<Mutex<Frame>>,
+    /// The frame filled on the emulation side while the window shows the other one.
+    frame: Frame,
+    /// Keys pressed (`true`) and released in the window, handled on the emulation side
+    /// which owns the keypad, the recording and the settings.
+    keys: Arc<Mutex<Vec<(Key, bool)>>>,
+    settings: EngineSettings,
+    display: Display,
+    recording: Recording,
+    /// With persistence, the screen is drawn at every frame while it fades.
+    afterglow: Option<Afterglow>,
 }
 
-const SCALE: u32 = 4;
-
-struct TaskData {
-    task: fn(&Data, &mut Vec<Rectangle>),
-    data: Option<Data>,
-}
-
-struct Data {
-    x: u32,
-    y: u32,
-    sprite_bytes: Vec<u8>,
+#[derive(Default)]
+struct Frame {
+    width: usize,
+    height: usize,
+    /// RGBA, row by row, one pixel of the display each.
+    pixels: Vec<u8>,
+    /// Not uploaded to the texture yet.
+    is_new: bool,
 }
 
 impl PistonInterface {
-    pub fn new() -> PistonInterface {
-        PistonInterface {
+    /// Opens the window on its own thread, and waits for it to know if it could.
+    pub fn new(settings: EngineSettings) -> Result<PistonInterface, String> {
+        let interface = PistonInterface {
             is_running: Arc::new(Mutex::new(true)),
-            task_data_queue: Arc::new(Mutex::new(VecDeque::new())),
-        }
-    }
-}
+            shared_frame: Arc::new(Mutex::new(Frame::default())),
+            frame: Frame::default(),
+            keys: Arc::new(Mutex::new(Vec::new())),
+            display: Display::new(),
+            recording: Recording::default(),
+            afterglow: settings.persistence.map(Afterglow::new),
+            settings,
+        };
 
-const BLACK: [f32; 4] = [0., 0., 0., 1.];
-const WHITE: [f32; 4] = [1., 1., 1., 1.];
-
-impl GraphicEngine for PistonInterface {
-    fn clear_screen(&mut self) {
-        self.task_data_queue.lock().unwrap().push_back(TaskData {
-            task: |_, rects| {
-                rects.clear();
-            },
-            data: None,
-        });
+        let (opened, is_open) = mpsc::channel();
+        interface.spawn_window(opened);
+        match is_open.recv() {
+            Ok(Ok(())) => Ok(interface),
+            Ok(Err(error)) => Err(format!("cannot open the window: {}", error)),
+            Err(_) => Err("cannot open the window".to_string()),
+        }
     }
 
-    fn draw_sprite(&mut self, x: u8, y: u8, sprite_bytes: &[u8]) -> bool {
-        println!("({} ; {}) {:?}", x, y, sprite_bytes);
+    /// Sends the last display to the window, through the afterglow if there is one.
+    fn present(&mut self) {
+        let display = &self.display;
+        let frame = &mut self.frame;
 
-        let mut vec_byte: Vec<u8> = Vec::new();
-
-        for byte in sprite_bytes {
-            vec_byte.push(*byte);
+        frame.width = display.width();
+        frame.height = display.height();
+        frame.pixels.clear();
+        for y in 0..display.height() {
+            for x in 0..display.width() {
+                let [r, g, b] = match self.afterglow {
+                    Some(ref afterglow) => afterglow.color(x, y),
+                    None => self.settings.palette.color(display.pixel(x, y)),
+                };
+                frame.pixels.extend_from_slice(&[r, g, b, 0xFF]);
+            }
         }
+        frame.is_new = true;
 
-        self.task_data_queue.lock().unwrap().push_back(TaskData {
-            task: |data, rects| {
-                for height in 0..data.sprite_bytes.len() - 1 {
-                    let bit = data.sprite_bytes[height];
-                    for index_bit in 0..8 {
-                        if bit & (1 << index_bit) > 0 {
-                            rects.push(rectangle::square(
-                                ((data.x + 7 - index_bit) * SCALE) as f64,
-                                ((data.y + height as u32) * SCALE) as f64,
-                                SCALE as f64,
-                            ));
-                        }
-                    }
-                }
-            },
-            data: Some(Data {
-                x: x as u32,
-                y: y as u32,
-                sprite_bytes: vec_byte,
-            }),
-        });
-
-        false
-    }
-
-    fn flush(&mut self) {}
-
-    fn is_running(&self) -> bool {
-        *self.is_running.lock().unwrap()
+        std::mem::swap(&mut *self.shared_frame.lock().unwrap(), frame);
     }
 
-    fn init_draw(&mut self) {
+    /// The window and its event loop, `opened` gets the result of its creation.
+    fn spawn_window(&self, opened: mpsc::Sender<Result<(), String>>) {
         let is_running = Arc::clone(&self.is_running);
-        let task_data_queue = Arc::clone(&self.task_data_queue);
+        let keys = Arc::clone(&self.keys);
+        let shared_frame = Arc::clone(&self.shared_frame);
+        let scale = self.settings.scale;
+        let scaling = self.settings.scaling;
+        let title = self.settings.title.clone();
 
         thread::spawn(move || {
             let opengl = OpenGL::V3_2;
 
-            let mut window: GlutinWindow = WindowSettings::new(
-                "Piston Chip8",
-                [super::SCREEN_WIDTH * SCALE, super::SCREEN_HEIGHT * SCALE],
-            )
-            .graphics_api(opengl)
-            .exit_on_esc(true)
-            .build()
-            .unwrap();
+            // winit panics instead of failing when there is no display
+            let hook = panic::take_hook();
+            panic::set_hook(Box::new(|_| {}));
+            let window = panic::catch_unwind(move || {
+                WindowSettings::new(
+                    title,
+                    [super::SCREEN_WIDTH * scale, super::SCREEN_HEIGHT * scale],
+                )
+                .graphics_api(opengl)
+                .exit_on_esc(true)
+                .resizable(true)
+                .build::<GlutinWindow>()
+                .map_err(|error| error.to_string())
+            });
+            panic::set_hook(hook);
+
+            let mut window = match window {
+                Ok(Ok(window)) => window,
+                Ok(Err(error)) => {
+                    let _ = opened.send(Err(error));
+                    return;
+                }
+                Err(panic) => {
+                    let error = match panic.downcast_ref::<&str>() {
+                        Some(message) => message.to_string(),
+                        None => panic.downcast_ref::<String>().cloned().unwrap_or_default(),
+                    };
+                    let _ = opened.send(Err(error));
+                    return;
+                }
+            };
+            let _ = opened.send(Ok(()));
 
             let mut gl = GlGraphics::new(opengl);
             let mut events = Events::new(EventSettings::new());
 
-            let mut rects: Vec<Rectangle> = Vec::new();
+            // the display, one texel per pixel, stretched over the viewport
+            let mut texture: Option<Texture> = None;
+            let texture_settings = TextureSettings::new().filter(Filter::Nearest);
+            let mut is_fullscreen = false;
 
             while let Some(e) = events.next(&mut window) {
-                if let Some(args) = e.render_args() {
-                    gl.draw(args.viewport(), |c, gl| {
-                        clear(BLACK, gl);
+                match e.press_args() {
+                    Some(Button::Keyboard(Key::F11)) => {
+                        is_fullscreen = !is_fullscreen;
+                        let glutin = window.ctx.window();
+                        glutin.set_fullscreen(if is_fullscreen {
+                            Some(glutin.get_current_monitor())
+                        } else {
+                            None
+                        });
+                    }
+                    Some(Button::Keyboard(key)) => keys.lock().unwrap().push((key, true)),
+                    _ => {}
+                }
+                if let Some(Button::Keyboard(key)) = e.release_args() {
+                    keys.lock().unwrap().push((key, false));
+                }
 
-                        let mut queue = task_data_queue.lock().unwrap();
-                        for task_data in queue.iter() {
-                            if let Some(ref data) = task_data.data {
-                                (task_data.task)(data, &mut rects);
+                if let Some(args) = e.render_args() {
+                    {
+                        let mut frame = shared_frame.lock().unwrap();
+                        if frame.is_new {
+                            frame.is_new = false;
+                            let size = [frame.width as u32, frame.height as u32];
+                            match texture {
+                                // same resolution, only the texels change
+                                Some(ref mut texture)
+                                    if texture.get_size() == (size[0], size[1]) =>
+                                {
+                                    UpdateTexture::update(
+                                        texture,
+                                        &mut (),
+                                        Format::Rgba8,
+                                        &frame.pixels,
+                                        [0, 0],
+                                        size,
+                                    )
+                                    .unwrap()
+                                }
+                                _ => {
+                                    texture = Some(
+                                        CreateTexture::create(
+                                            &mut (),
+                                            Format::Rgba8,
+                                            &frame.pixels,
+                                            size,
+                                            &texture_settings,
+                                        )
+                                        .unwrap(),
+                                    )
+                                }
                             }
                         }
-                        queue.clear();
+                    }
 
-                        for rect in &rects {
-                            rectangle(WHITE, *rect, c.transform, gl);
+                    gl.draw(args.viewport(), |c, gl| {
+                        // black bars around the display
+                        clear([0., 0., 0., 1.], gl);
+
+                        if let Some(ref texture) = texture {
+                            let (width, height) = texture.get_size();
+                            let viewport = Viewport::new(
+                                (args.window_size[0], args.window_size[1]),
+                                width as usize,
+                                height as usize,
+                                scaling,
+                            );
+                            Image::new()
+                                .rect([viewport.x, viewport.y, viewport.width, viewport.height])
+                                .draw(texture, &c.draw_state, c.transform, gl);
                         }
                     })
                 }
@@ -133,4 +218,63 @@ impl GraphicEngine for PistonInterface {
             *is_running.lock().unwrap() = false;
         });
     }
+
+    fn keypad_key(&self, key: Key) -> Option<usize> {
+        match std::char::from_u32(key as u32) {
+            Some(c) if c.is_ascii() => self.settings.keymap.key(c).map(|key| key as usize),
+            _ => None,
+        }
+    }
+}
+
+impl GraphicEngine for PistonInterface {
+    fn draw(&mut self, display: &Display) {
+        self.display = display.clone();
+        // the afterglow is drawn once per frame, by `flush`
+        if self.afterglow.is_none() {
+            self.present();
+        }
+    }
+
+    fn flush(&mut self, keypad: &mut [bool; 16]) {
+        let has_faded = match self.afterglow {
+            Some(ref mut afterglow) => afterglow.update(&self.display, &self.settings.palette),
+            None => false,
+        };
+        if has_faded {
+            self.present();
+        }
+
+        let keys = std::mem::take(&mut *self.keys.lock().unwrap());
+        for (key, is_pressed) in keys {
+            match key {
+                Key::F12 if is_pressed => screenshot::take(
+                    &self.display,
+                    &self.settings.palette,
+                    self.settings.scale,
+                    &self.settings.name,
+                ),
+                Key::F10 if is_pressed => println!("{}", self.recording.toggle(&self.settings)),
+                Key::F9 if is_pressed => {
+                    self.settings.palette = self.settings.palette.next();
+                    println!("Palette: {}.", self.settings.palette.description());
+                    self.present();
+                }
+                _ => {
+                    if let Some(key) = self.keypad_key(key) {
+                        keypad[key] = is_pressed;
+                    }
+                }
+            }
+        }
+        if let Err(error) = self.recording.add_frame(&self.display) {
+            eprintln!("error: {}", error);
+        }
+    }
+
+    fn is_running(&self) -> bool {
+        *self.is_running.lock().unwrap()
+    }
+
+    fn init_draw(&mut self) {}
 }
@@ -0,0 +1,150 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// The machine being emulated, each one extends the instruction set of the previous one.
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug)]
+pub enum Platform {
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+impl Platform {
+    pub fn memory_size(self) -> usize {
+        match self {
+            Platform::Chip8 | Platform::SuperChip => 0x1000,
+            Platform::XoChip => 0x10000,
+        }
+    }
+
+    /// The behaviour programs written for the platform expect.
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Platform::Chip8 => Quirks {
+                vf_reset: true,
+                memory: true,
+                display_wait: true,
+                clipping: true,
+                shifting: false,
+                jumping: false,
+            },
+            Platform::SuperChip => Quirks {
+                vf_reset: false,
+                memory: false,
+                display_wait: false,
+                clipping: true,
+                shifting: true,
+                jumping: true,
+            },
+            Platform::XoChip => Quirks {
+                vf_reset: false,
+                memory: true,
+                display_wait: false,
+                clipping: false,
+                shifting: false,
+                jumping: false,
+            },
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Platform::Chip8 => "CHIP-8",
+            Platform::SuperChip => "SuperChip",
+            Platform::XoChip => "XO-CHIP",
+        })
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Platform, String> {
+        match name.to_lowercase().as_str() {
+            "chip8" | "chip-8" | "vip" => Ok(Platform::Chip8),
+            "schip" | "superchip" => Ok(Platform::SuperChip),
+            "xochip" | "xo-chip" => Ok(Platform::XoChip),
+            _ => Err(format!(
+                "unknown platform '{}', expected chip8, schip or xochip",
+                name
+            )),
+        }
+    }
+}
+
+/// The behaviours CHIP-8 implementations disagree on.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Quirks {
+    /// 8XY1, 8XY2 and 8XY3 reset VF to 0.
+    pub vf_reset: bool,
+    /// FX55 and FX65 leave I pointing after the last register saved or loaded.
+    pub memory: bool,
+    /// DXYN waits for the next frame before drawing.
+    pub display_wait: bool,
+    /// Sprites are clipped at the edges of the screen instead of wrapping around.
+    pub clipping: bool,
+    /// 8XY6 and 8XYE shift VX in place, ignoring VY.
+    pub shifting: bool,
+    /// BNNN jumps to NNN plus VX (X being the highest nibble of NNN) instead of V0.
+    pub jumping: bool,
+}
+
+const QUIRK_NAMES: [&str; 6] = [
+    "vf-reset",
+    "memory",
+    "display-wait",
+    "clipping",
+    "shifting",
+    "jumping",
+];
+
+impl Quirks {
+    fn quirk(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "vf-reset" => Some(&mut self.vf_reset),
+            "memory" => Some(&mut self.memory),
+            "display-wait" => Some(&mut self.display_wait),
+            "clipping" => Some(&mut self.clipping),
+            "shifting" => Some(&mut self.shifting),
+            "jumping" => Some(&mut self.jumping),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Quirks {
+    type Err = String;
+
+    /// A platform preset optionally followed by quirks to enable or disable,
+    /// like `schip,-clipping,+display-wait`.
+    fn from_str(text: &str) -> Result<Quirks, String> {
+        let mut items = text.split(',').map(str::trim);
+        let mut quirks = items
+            .next()
+            .unwrap_or_default()
+            .parse::<Platform>()?
+            .quirks();
+
+        for item in items {
+            let (enabled, name) = match (item.strip_prefix('+'), item.strip_prefix('-')) {
+                (Some(name), _) => (true, name),
+                (_, Some(name)) => (false, name),
+                _ => (true, item),
+            };
+            match quirks.quirk(name) {
+                Some(quirk) => *quirk = enabled,
+                None => {
+                    return Err(format!(
+                        "unknown quirk '{}', expected one of {}",
+                        name,
+                        QUIRK_NAMES.join(", ")
+                    ))
+                }
+            }
+        }
+
+        Ok(quirks)
+    }
+}
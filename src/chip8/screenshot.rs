@@ -0,0 +1,133 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use super::display::{Display, HIRES_HEIGHT, HIRES_WIDTH};
+use super::graphic_engine::Palette;
+use super::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+/// An RGB picture of the display, 3 bytes per pixel, row by row.
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+/// Size in the picture of a pixel of a display `width` pixels wide,
+/// a hires pixel is half a lores one like on the screen.
+pub fn pixel_size(width: usize, scale: u32) -> usize {
+    (scale * super::SCREEN_WIDTH / width as u32).max(1) as usize
+}
+
+/// Size of the pictures of a whole recording, big enough for both resolutions at `scale`.
+pub fn canvas_size(scale: u32) -> (usize, usize) {
+    let (lores, hires) = (
+        pixel_size(SCREEN_WIDTH as usize, scale),
+        pixel_size(HIRES_WIDTH as usize, scale),
+    );
+    (
+        (SCREEN_WIDTH as usize * lores).max(HIRES_WIDTH as usize * hires),
+        (SCREEN_HEIGHT as usize * lores).max(HIRES_HEIGHT as usize * hires),
+    )
+}
+
+/// The planes of every pixel of a `width` x `height` picture, with the display at the top left.
+pub fn canvas(display: &Display, scale: u32, width: usize, height: usize) -> Vec<u8> {
+    let size = pixel_size(display.width(), scale);
+    let mut planes = vec![0; width * height];
+
+    for y in 0..(display.height() * size).min(height) {
+        for x in 0..(display.width() * size).min(width) {
+            planes[y * width + x] = display.pixel(x / size, y / size) & 0b11;
+        }
+    }
+    planes
+}
+
+impl Image {
+    pub fn new(display: &Display, palette: &Palette, scale: u32) -> Image {
+        let size = pixel_size(display.width(), scale);
+        let (width, height) = (display.width() * size, display.height() * size);
+        let mut pixels = Vec::with_capacity(width * height * 3);
+
+        for y in 0..height {
+            for x in 0..width {
+                pixels.extend_from_slice(&palette.color(display.pixel(x / size, y / size)));
+            }
+        }
+
+        Image {
+            width: width as u32,
+            height: height as u32,
+            pixels,
+        }
+    }
+}
+
+/// Writes the display to `path`, as a 1-bit PBM if the extension is `.pbm`
+/// and as a PNG otherwise.
+pub fn save(display: &Display, palette: &Palette, scale: u32, path: &Path) -> Result<(), String> {
+    let is_pbm = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("pbm"));
+
+    let file = File::create(path)
+        .map_err(|error| format!("cannot create '{}': {}", path.display(), error))?;
+    let mut writer = BufWriter::new(file);
+
+    if is_pbm {
+        write_pbm(&mut writer, display, scale).map_err(|error| error.to_string())
+    } else {
+        write_png(&mut writer, &Image::new(display, palette, scale))
+    }
+    .map_err(|error| format!("cannot write '{}': {}", path.display(), error))
+}
+
+fn write_png<W: Write>(writer: W, image: &Image) -> Result<(), String> {
+    let mut encoder = png::Encoder::new(writer, image.width, image.height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&image.pixels))
+        .map_err(|error| error.to_string())
+}
+
+/// Binary PBM, every lit pixel is black whatever its planes, like ink on paper.
+fn write_pbm<W: Write>(mut writer: W, display: &Display, scale: u32) -> std::io::Result<()> {
+    let size = pixel_size(display.width(), scale);
+    let (width, height) = (display.width() * size, display.height() * size);
+    write!(writer, "P4\n{} {}\n", width, height)?;
+
+    // every row is padded to a whole byte
+    let mut row = vec![0u8; width.div_ceil(8)];
+    for y in 0..height {
+        row.iter_mut().for_each(|byte| *byte = 0);
+        for x in 0..width {
+            if display.pixel(x / size, y / size) != 0 {
+                row[x / 8] |= 0x80 >> (x % 8);
+            }
+        }
+        writer.write_all(&row)?;
+    }
+
+    writer.flush()
+}
+
+/// The first `<name>-<n>.<extension>` that doesn't exist yet, for the hotkeys.
+pub fn next_path(name: &str, extension: &str) -> PathBuf {
+    (1..)
+        .map(|n| PathBuf::from(format!("{}-{}.{}", name, n, extension)))
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+/// Saves a screenshot from the hotkey of a window, which has nowhere to report errors.
+pub fn take(display: &Display, palette: &Palette, scale: u32, name: &str) {
+    let path = next_path(name, "png");
+    match save(display, palette, scale, &path) {
+        Ok(()) => println!("Saved the screenshot '{}'.", path.display()),
+        Err(error) => eprintln!("error: {}", error),
+    }
+}
@@ -1,75 +1,189 @@
-use super::graphic_engine::GraphicEngine;
+use super::display::Display;
+use super::gif_recorder::Recording;
+use super::graphic_engine::{EngineSettings, GraphicEngine, Renderer};
+use super::persistence::Afterglow;
+use super::screenshot;
+use super::viewport::Viewport;
 use sdl2::{
-    event::Event, keyboard::Keycode, pixels::Color, rect::Rect, render::Canvas, video::Window,
+    event::{Event, WindowEvent},
+    keyboard::Keycode,
+    pixels::{Color, PixelFormatEnum},
+    rect::Rect,
+    render::{Canvas, Texture},
+    video::{FullscreenType, Window},
     EventPump,
 };
 
 pub struct SdlInterface {
     canvas: Canvas<Window>,
+    /// The display, one texel per pixel, stretched over the viewport. Without a
+    /// lifetime (`unsafe_textures`), it is freed with the canvas.
+    texture: Option<Texture>,
     event_pump: EventPump,
     is_running: bool,
+    settings: EngineSettings,
+    /// The last drawn display, for screenshots and recordings.
+    display: Display,
+    /// The screen has to be drawn again at the end of the frame.
+    has_changed: bool,
+    recording: Recording,
+    /// With persistence, the screen is drawn at every frame while it fades.
+    afterglow: Option<Afterglow>,
 }
 
-const SCALE: u32 = 4;
-
 impl SdlInterface {
-    pub fn new() -> SdlInterface {
-        let sdl_context = sdl2::init().unwrap();
-        let video_subsystem = sdl_context.video().unwrap();
+    pub fn new(settings: EngineSettings) -> Result<SdlInterface, String> {
+        let error = |error: String| format!("cannot open the window: {}", error);
+
+        let sdl_context = sdl2::init().map_err(error)?;
+        let video_subsystem = sdl_context.video().map_err(error)?;
+        // sharp pixels when the texture is stretched
+        sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", "nearest");
 
         let window = video_subsystem
             .window(
-                "chip8",
-                super::SCREEN_WIDTH * SCALE,
-                super::SCREEN_HEIGHT * SCALE,
+                &settings.title,
+                super::SCREEN_WIDTH * settings.scale,
+                super::SCREEN_HEIGHT * settings.scale,
             )
             .position_centered()
+            .resizable()
             .build()
-            .unwrap();
+            .map_err(|window_error| error(window_error.to_string()))?;
 
-        let canvas = window.into_canvas().build().unwrap();
+        let canvas = match settings.renderer {
+            Renderer::Accelerated => window.into_canvas().accelerated().present_vsync(),
+            Renderer::Software => window.into_canvas().software(),
+        }
+        .build()
+        .map_err(|canvas_error| error(canvas_error.to_string()))?;
 
-        let event_pump = sdl_context.event_pump().unwrap();
+        let event_pump = sdl_context.event_pump().map_err(error)?;
 
-        SdlInterface {
+        Ok(SdlInterface {
             canvas,
+            texture: None,
             event_pump,
             is_running: true,
-        }
+            display: Display::new(),
+            has_changed: true,
+            recording: Recording::default(),
+            afterglow: settings.persistence.map(Afterglow::new),
+            settings,
+        })
     }
-}
 
-impl GraphicEngine for SdlInterface {
-    fn clear_screen(&mut self) {
-        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
-        self.canvas.clear();
+    fn color(&self, planes: u8) -> Color {
+        let [r, g, b] = self.settings.palette.color(planes);
+        Color::RGB(r, g, b)
     }
 
-    fn draw_sprite(&mut self, x: u8, y: u8, sprite_bytes: &[u8]) -> bool {
-        self.canvas.set_draw_color(Color::RGB(255, 255, 255));
-
-        println!("({} ; {}) {:?}", x, y, sprite_bytes);
-
-        for height in 0..sprite_bytes.len() - 1 {
-            let bit = sprite_bytes[height];
-            for index_bit in 0..8 {
-                if bit & (1 << index_bit) > 0 {
-                    self.canvas
-                        .fill_rect(Rect::new(
-                            (x as i32 + 7 - index_bit) * SCALE as i32,
-                            (y as i32 + height as i32) * SCALE as i32,
-                            SCALE,
-                            SCALE,
-                        ))
-                        .unwrap();
+    /// Copies the last display into the texture, through the afterglow if there is one.
+    fn update_texture(&mut self) -> Result<(), String> {
+        let (width, height) = (self.display.width(), self.display.height());
+
+        // a new texture when the resolution changes
+        let texture = match self.texture {
+            Some(ref mut texture)
+                if (texture.query().width, texture.query().height)
+                    == (width as u32, height as u32) =>
+            {
+                texture
+            }
+            _ => {
+                let texture = self
+                    .canvas
+                    .create_texture_streaming(PixelFormatEnum::RGB24, width as u32, height as u32)
+                    .map_err(|error| error.to_string())?;
+                if let Some(old) = self.texture.replace(texture) {
+                    // the canvas that created it is still alive
+                    unsafe { old.destroy() };
                 }
+                self.texture.as_mut().unwrap()
             }
+        };
+
+        let display = &self.display;
+        let afterglow = &self.afterglow;
+        let palette = &self.settings.palette;
+        texture.with_lock(None, |buffer, pitch| {
+            for y in 0..height {
+                for x in 0..width {
+                    let rgb = match afterglow {
+                        Some(afterglow) => afterglow.color(x, y),
+                        None => palette.color(display.pixel(x, y)),
+                    };
+                    let offset = y * pitch + x * 3;
+                    buffer[offset..offset + 3].copy_from_slice(&rgb);
+                }
+            }
+        })
+    }
+
+    /// Draws the texture in the middle of the window with black bars around.
+    fn present(&mut self) {
+        if let Err(error) = self.update_texture() {
+            eprintln!("error: {}", error);
+            return;
+        }
+
+        let (width, height) = match self.canvas.output_size() {
+            Ok(size) => size,
+            Err(error) => {
+                eprintln!("error: {}", error);
+                return;
+            }
+        };
+        let viewport = Viewport::new(
+            (width as f64, height as f64),
+            self.display.width(),
+            self.display.height(),
+            self.settings.scaling,
+        );
+
+        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        self.canvas.clear();
+        if let Some(ref texture) = self.texture {
+            let target = Rect::new(
+                viewport.x as i32,
+                viewport.y as i32,
+                viewport.width as u32,
+                viewport.height as u32,
+            );
+            if let Err(error) = self.canvas.copy(texture, None, target) {
+                eprintln!("error: {}", error);
+            }
+        }
+        self.canvas.present();
+        self.has_changed = false;
+    }
+
+    fn keypad_key(&self, keycode: Keycode) -> Option<usize> {
+        let name = keycode.name();
+        let mut chars = name.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => self.settings.keymap.key(c).map(|key| key as usize),
+            _ => None,
         }
-        false
     }
+}
 
-    fn flush(&mut self) {
-        for event in self.event_pump.poll_iter() {
+impl GraphicEngine for SdlInterface {
+    fn draw(&mut self, display: &Display) {
+        self.display = display.clone();
+        // the texture is updated once per frame, by `flush`
+        self.has_changed = true;
+    }
+
+    fn flush(&mut self, keypad: &mut [bool; 16]) {
+        if let Some(ref mut afterglow) = self.afterglow {
+            // with persistence, the screen changes as long as it fades
+            self.has_changed = afterglow.update(&self.display, &self.settings.palette);
+        }
+
+        let events: Vec<Event> = self.event_pump.poll_iter().collect();
+
+        for event in events {
             match event {
                 Event::Quit { .. }
                 | Event::KeyDown {
@@ -78,11 +192,70 @@ impl GraphicEngine for SdlInterface {
                 } => {
                     self.is_running = false;
                 }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F12),
+                    ..
+                } => screenshot::take(
+                    &self.display,
+                    &self.settings.palette,
+                    self.settings.scale,
+                    &self.settings.name,
+                ),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F10),
+                    ..
+                } => println!("{}", self.recording.toggle(&self.settings)),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F11),
+                    ..
+                } => {
+                    let window = self.canvas.window_mut();
+                    let fullscreen = match window.fullscreen_state() {
+                        FullscreenType::Off => FullscreenType::Desktop,
+                        _ => FullscreenType::Off,
+                    };
+                    if let Err(error) = window.set_fullscreen(fullscreen) {
+                        eprintln!("error: cannot change the fullscreen mode: {}", error);
+                    }
+                }
+                Event::Window {
+                    win_event: WindowEvent::SizeChanged(..),
+                    ..
+                } => self.has_changed = true,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F9),
+                    ..
+                } => {
+                    self.settings.palette = self.settings.palette.next();
+                    println!("Palette: {}.", self.settings.palette.description());
+                    self.has_changed = true;
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    ..
+                } => {
+                    if let Some(key) = self.keypad_key(keycode) {
+                        keypad[key] = true;
+                    }
+                }
+                Event::KeyUp {
+                    keycode: Some(keycode),
+                    ..
+                } => {
+                    if let Some(key) = self.keypad_key(keycode) {
+                        keypad[key] = false;
+                    }
+                }
                 _ => {}
             }
         }
 
-        self.canvas.present();
+        if self.has_changed {
+            self.present();
+        }
+        if let Err(error) = self.recording.add_frame(&self.display) {
+            eprintln!("error: {}", error);
+        }
     }
 
     fn is_running(&self) -> bool {
@@ -90,7 +263,8 @@ impl GraphicEngine for SdlInterface {
     }
 
     fn init_draw(&mut self) {
-        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        self.canvas.set_draw_color(self.color(0));
         self.canvas.clear();
+        self.canvas.present();
     }
 }
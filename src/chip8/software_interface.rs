@@ -0,0 +1,175 @@
+use minifb::{Key, KeyRepeat, Window, WindowOptions};
+
+use super::display::Display;
+use super::gif_recorder::Recording;
+use super::graphic_engine::{EngineSettings, GraphicEngine};
+use super::persistence::Afterglow;
+use super::screenshot;
+use super::viewport::Viewport;
+
+/// A window drawn by the CPU and blitted by X11 (or XWayland),
+/// for the machines without GPU drivers or OpenGL 3.2.
+/// There is no fullscreen toggle, the window can be maximised instead.
+pub struct SoftwareInterface {
+    window: Window,
+    /// The window pixels, `0RGB` row by row.
+    buffer: Vec<u32>,
+    /// The window size when it was last drawn.
+    size: (usize, usize),
+    settings: EngineSettings,
+    is_running: bool,
+    /// The last drawn display, for screenshots and recordings.
+    display: Display,
+    /// The screen has to be drawn again at the end of the frame.
+    has_changed: bool,
+    recording: Recording,
+    /// With persistence, the screen is drawn at every frame while it fades.
+    afterglow: Option<Afterglow>,
+}
+
+impl SoftwareInterface {
+    pub fn new(settings: EngineSettings) -> Result<SoftwareInterface, String> {
+        let mut window = Window::new(
+            &settings.title,
+            (super::SCREEN_WIDTH * settings.scale) as usize,
+            (super::SCREEN_HEIGHT * settings.scale) as usize,
+            WindowOptions {
+                resize: true,
+                ..WindowOptions::default()
+            },
+        )
+        .map_err(|error| format!("cannot open the window: {}", error))?;
+        // the frames are already timed by the emulation
+        window.set_target_fps(0);
+
+        Ok(SoftwareInterface {
+            window,
+            buffer: Vec::new(),
+            size: (0, 0),
+            is_running: true,
+            display: Display::new(),
+            has_changed: true,
+            recording: Recording::default(),
+            afterglow: settings.persistence.map(Afterglow::new),
+            settings,
+        })
+    }
+
+    /// Draws the last display, through the afterglow if there is one,
+    /// in the middle of the window with black bars around.
+    fn present(&mut self) {
+        let (width, height) = self.window.get_size();
+        self.size = (width, height);
+        // minimised
+        if width == 0 || height == 0 {
+            self.window.update();
+            return;
+        }
+
+        let display = &self.display;
+        let viewport = Viewport::new(
+            (width as f64, height as f64),
+            display.width(),
+            display.height(),
+            self.settings.scaling,
+        );
+
+        self.buffer.clear();
+        self.buffer.resize(width * height, 0);
+        for y in 0..display.height() {
+            for x in 0..display.width() {
+                let [r, g, b] = match self.afterglow {
+                    Some(ref afterglow) => afterglow.color(x, y),
+                    None => self.settings.palette.color(display.pixel(x, y)),
+                };
+                let color = u32::from_be_bytes([0, r, g, b]);
+
+                let [left, top, rect_width, rect_height] = viewport.pixel_rect(x, y);
+                let (left, top) = (left.max(0.) as usize, top.max(0.) as usize);
+                let right = (left + rect_width as usize).min(width);
+                let bottom = (top + rect_height as usize).min(height);
+                for row in top..bottom {
+                    self.buffer[row * width + left..row * width + right].fill(color);
+                }
+            }
+        }
+
+        if let Err(error) = self.window.update_with_buffer(&self.buffer, width, height) {
+            eprintln!("error: {}", error);
+        }
+        self.has_changed = false;
+    }
+
+    fn keypad_key(&self, key: Key) -> Option<usize> {
+        let c = match key as u8 {
+            digit @ 0..=9 => (b'0' + digit) as char,
+            letter @ 10..=35 => (b'a' + letter - 10) as char,
+            _ => return None,
+        };
+        self.settings.keymap.key(c).map(|key| key as usize)
+    }
+}
+
+impl GraphicEngine for SoftwareInterface {
+    fn draw(&mut self, display: &Display) {
+        self.display = display.clone();
+        // the window is updated once per frame, by `flush`
+        self.has_changed = true;
+    }
+
+    fn flush(&mut self, keypad: &mut [bool; 16]) {
+        if let Some(ref mut afterglow) = self.afterglow {
+            // with persistence, the screen changes as long as it fades
+            self.has_changed = afterglow.update(&self.display, &self.settings.palette);
+        }
+
+        if !self.window.is_open() || self.window.is_key_down(Key::Escape) {
+            self.is_running = false;
+        }
+
+        for key in self.window.get_keys_pressed(KeyRepeat::No) {
+            match key {
+                Key::F12 => screenshot::take(
+                    &self.display,
+                    &self.settings.palette,
+                    self.settings.scale,
+                    &self.settings.name,
+                ),
+                Key::F10 => println!("{}", self.recording.toggle(&self.settings)),
+                Key::F9 => {
+                    self.settings.palette = self.settings.palette.next();
+                    println!("Palette: {}.", self.settings.palette.description());
+                    self.has_changed = true;
+                }
+                _ => {}
+            }
+        }
+
+        // the window gives the keys held, not the events
+        *keypad = [false; 16];
+        for key in self.window.get_keys() {
+            if let Some(key) = self.keypad_key(key) {
+                keypad[key] = true;
+            }
+        }
+
+        if self.has_changed || self.window.get_size() != self.size {
+            self.present();
+        } else {
+            // the events are read when the window is updated
+            self.window.update();
+        }
+
+        if let Err(error) = self.recording.add_frame(&self.display) {
+            eprintln!("error: {}", error);
+        }
+    }
+
+    fn is_running(&self) -> bool {
+        self.is_running
+    }
+
+    fn init_draw(&mut self) {
+        self.present();
+    }
+}
@@ -0,0 +1,338 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+
+use super::display::{HIRES_HEIGHT, HIRES_WIDTH};
+use super::graphic_engine::GraphicEngine;
+use super::quirks::{Platform, Quirks};
+use super::{Chip8, REGISTER_SIZE, STACK_SIZE};
+
+const MAGIC: &[u8; 4] = b"C8ST";
+/// Changes when the layout of the states does.
+const VERSION: u8 = 1;
+
+/// Everything but the memory and the display.
+const FIXED_SIZE: usize = MAGIC.len() + 1 // version
+    + 1 + 6 + 4 // platform, quirks, speed
+    + 4 + 4 // memory and display sizes
+    + REGISTER_SIZE + 4 + 1 + STACK_SIZE * 4 // registers, I, stack
+    + 1 + 1 + 4 + 1 + 1 + 8 // timers, PC, blocked, on, cycle
+    + 1 + 1 // hires, planes
+    + 16 + 16 + 1 // keypads, released key
+    + REGISTER_SIZE + 16 + 1 // flags, pattern, pitch
+    + 1 + 8 // drawn, frame start
+    + 8 + 16; // seed, position of the random numbers
+
+/// Save states: the whole machine in a binary snapshot, without the engine,
+/// the tracer and the audio recorder which belong to the host.
+impl<E: GraphicEngine + ?Sized> Chip8<E> {
+    /// The largest state of the current platform, the states are often smaller.
+    pub fn state_size(&self) -> usize {
+        FIXED_SIZE + self.ram.len() + (HIRES_WIDTH * HIRES_HEIGHT) as usize
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = Writer(Vec::with_capacity(self.state_size()));
+        state.bytes(MAGIC);
+        state.u8(VERSION);
+
+        state.u8(match self.platform {
+            Platform::Chip8 => 0,
+            Platform::SuperChip => 1,
+            Platform::XoChip => 2,
+        });
+        let quirks = &self.quirks;
+        for quirk in [
+            quirks.vf_reset,
+            quirks.memory,
+            quirks.display_wait,
+            quirks.clipping,
+            quirks.shifting,
+            quirks.jumping,
+        ] {
+            state.bool(quirk);
+        }
+        state.u32(self.cycles_per_frame);
+
+        state.u32(self.ram.len() as u32);
+        state.u32(self.display.pixels().len() as u32);
+        state.bytes(&self.ram);
+
+        state.bytes(&self.v);
+        state.u32(self.i as u32);
+        state.u8(self.stack.len() as u8);
+        for index in 0..STACK_SIZE {
+            state.u32(self.stack.get(index).copied().unwrap_or(0) as u32);
+        }
+        state.u8(self.delay_timer);
+        state.u8(self.sound_timer);
+        state.u32(self.pc as u32);
+        state.bool(self.is_pc_blocked);
+        state.bool(self.is_on);
+        state.u64(self.cycle);
+
+        state.bool(self.display.is_hires());
+        state.bytes(self.display.pixels());
+        state.u8(self.planes);
+
+        for &key in self.keypad.iter().chain(self.frame_keypad.iter()) {
+            state.bool(key);
+        }
+        state.u8(self.released_key.unwrap_or(0xFF));
+        state.bytes(&self.flags);
+        state.bytes(&self.audio_pattern);
+        state.u8(self.pitch);
+        state.bool(self.has_drawn);
+        state.u64(self.frame_start_cycle);
+
+        state.u64(self.seed);
+        state.u128(self.rng.get_word_pos());
+
+        state.0
+    }
+
+    /// Puts the machine back in a state made by `save_state`, trailing bytes are ignored.
+    /// Nothing changes when the state is invalid.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), String> {
+        let mut state = Reader { state, position: 0 };
+        if state.bytes(MAGIC.len())? != MAGIC {
+            return Err("this isn't a save state".to_string());
+        }
+        let version = state.u8()?;
+        if version != VERSION {
+            return Err(format!(
+                "the save state has the version {}, only {} is supported",
+                version, VERSION
+            ));
+        }
+
+        let platform = match state.u8()? {
+            0 => Platform::Chip8,
+            1 => Platform::SuperChip,
+            2 => Platform::XoChip,
+            platform => return Err(format!("unknown platform {} in the save state", platform)),
+        };
+        let quirks = Quirks {
+            vf_reset: state.bool()?,
+            memory: state.bool()?,
+            display_wait: state.bool()?,
+            clipping: state.bool()?,
+            shifting: state.bool()?,
+            jumping: state.bool()?,
+        };
+        let cycles_per_frame = state.u32()?;
+
+        let ram_size = state.u32()? as usize;
+        let pixel_count = state.u32()? as usize;
+        if ram_size != platform.memory_size() {
+            return Err(format!(
+                "the save state has {} bytes of memory instead of {} for {}",
+                ram_size,
+                platform.memory_size(),
+                platform
+            ));
+        }
+        let ram = state.bytes(ram_size)?.to_vec();
+
+        let mut v = [0; REGISTER_SIZE];
+        v.copy_from_slice(state.bytes(REGISTER_SIZE)?);
+        let i = state.u32()? as usize;
+        let stack_size = (state.u8()? as usize).min(STACK_SIZE);
+        let mut stack = Vec::with_capacity(STACK_SIZE);
+        for index in 0..STACK_SIZE {
+            let address = state.u32()? as usize;
+            if index < stack_size {
+                stack.push(address);
+            }
+        }
+        let delay_timer = state.u8()?;
+        let sound_timer = state.u8()?;
+        let pc = state.u32()? as usize;
+        let is_pc_blocked = state.bool()?;
+        let is_on = state.bool()?;
+        let cycle = state.u64()?;
+
+        let mut display = self.display.clone();
+        let is_hires = state.bool()?;
+        display.restore(is_hires, state.bytes(pixel_count)?)?;
+        let planes = state.u8()?;
+
+        let mut keypads = [false; 32];
+        for key in keypads.iter_mut() {
+            *key = state.bool()?;
+        }
+        let released_key = match state.u8()? {
+            0xFF => None,
+            key => Some(key & 0xF),
+        };
+        let mut flags = [0; REGISTER_SIZE];
+        flags.copy_from_slice(state.bytes(REGISTER_SIZE)?);
+        let mut audio_pattern = [0; 16];
+        audio_pattern.copy_from_slice(state.bytes(16)?);
+        let pitch = state.u8()?;
+        let has_drawn = state.bool()?;
+        let frame_start_cycle = state.u64()?;
+
+        let seed = state.u64()?;
+        let mut rng = ChaCha20Rng::seed_from_u64(seed);
+        rng.set_word_pos(state.u128()?);
+
+        // everything was read, the state is valid
+        self.platform = platform;
+        self.quirks = quirks;
+        self.cycles_per_frame = cycles_per_frame;
+        // in place when it can, the frontends may hold pointers to the memory
+        if self.ram.len() == ram.len() {
+            self.ram.copy_from_slice(&ram);
+        } else {
+            self.ram = ram;
+        }
+        self.v = v;
+        self.i = i;
+        self.stack = stack;
+        self.delay_timer = delay_timer;
+        self.sound_timer = sound_timer;
+        self.pc = pc;
+        self.is_pc_blocked = is_pc_blocked;
+        self.is_on = is_on;
+        self.halt_reason = None;
+        self.cycle = cycle;
+        self.display = display;
+        self.planes = planes;
+        self.keypad.copy_from_slice(&keypads[..16]);
+        self.frame_keypad.copy_from_slice(&keypads[16..]);
+        self.released_key = released_key;
+        self.flags = flags;
+        self.audio_pattern = audio_pattern;
+        self.pitch = pitch;
+        self.has_drawn = has_drawn;
+        self.frame_start_cycle = frame_start_cycle;
+        self.seed = seed;
+        self.rng = rng;
+        Ok(())
+    }
+}
+
+/// Little endian values one after the other.
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u128(&mut self, value: u128) {
+        self.bytes(&value.to_le_bytes());
+    }
+}
+
+struct Reader<'a> {
+    state: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .state
+            .get(self.position..self.position + count)
+            .ok_or_else(|| "the save state is truncated".to_string())?;
+        self.position += count;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, String> {
+        Ok(self.u8()? != 0)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.bytes(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn u128(&mut self) -> Result<u128, String> {
+        let mut bytes = [0; 16];
+        bytes.copy_from_slice(self.bytes(16)?);
+        Ok(u128::from_le_bytes(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::headless_interface::HeadlessInterface;
+
+    /// Draws random numbers in a loop, counting the turns in V2.
+    const RANDOM_LOOP: &[u8] = &[0xC0, 0xFF, 0xC1, 0xFF, 0x72, 0x01, 0x12, 0x00];
+
+    fn running(seed: u64, cycle: u64) -> Chip8<HeadlessInterface> {
+        let mut chip = Chip8::headless();
+        chip.set_seed(seed);
+        chip.load(RANDOM_LOOP).unwrap();
+        chip.start();
+        chip.run_to_cycle(cycle);
+        chip
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut chip = running(7, 50);
+        let state = chip.save_state();
+        assert!(state.len() <= chip.state_size());
+        chip.run_to_cycle(100);
+
+        // another seed, the state brings the position of the random numbers back
+        let mut restored = running(8, 10);
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.save_state(), state);
+        restored.run_to_cycle(100);
+        assert_eq!(restored.registers(), chip.registers());
+        assert_eq!(restored.pc(), chip.pc());
+        assert_eq!(restored.save_state(), chip.save_state());
+    }
+
+    #[test]
+    fn invalid_states() {
+        let chip = running(7, 50);
+        let state = chip.save_state();
+
+        let mut other_platform = state.clone();
+        other_platform[MAGIC.len() + 1] = 2;
+        let truncated = &state[..state.len() - 1];
+
+        let mut changed = running(8, 30);
+        let before = changed.save_state();
+        assert!(changed.load_state(truncated).is_err());
+        assert!(changed.load_state(&other_platform).is_err());
+        assert!(changed.load_state(b"C8ST").is_err());
+        assert_eq!(changed.save_state(), before);
+        changed.run_to_cycle(50);
+        assert_ne!(changed.registers(), chip.registers());
+    }
+}
@@ -0,0 +1,358 @@
+use std::io::{self, Stdout, Write};
+use std::time::Duration;
+
+use crossterm::{
+    cursor,
+    event::{
+        self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+        PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
+    queue,
+    style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor},
+    terminal::{self, ClearType},
+};
+
+use super::display::Display;
+use super::gif_recorder::Recording;
+use super::graphic_engine::{EngineSettings, GraphicEngine};
+use super::screenshot;
+
+/// Without key release events, a key is released this number of frames after
+/// its last press, long enough to reach the autorepeat of the terminal.
+const KEY_HOLD_FRAMES: u8 = 10;
+
+/// How the pixels fit in the characters of the terminal.
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    /// `▀` with the top pixel as foreground and the bottom one as background, 1x2 pixels.
+    HalfBlocks,
+    /// Braille patterns, 2x4 pixels of one colour.
+    Braille,
+    TooSmall,
+}
+
+/// Draws in the terminal, for machines with no display like over SSH.
+pub struct TerminalInterface {
+    stdout: Stdout,
+    settings: EngineSettings,
+    is_running: bool,
+    /// The terminal reports key releases (kitty keyboard protocol).
+    has_key_releases: bool,
+    held_keys: [u8; 16],
+    size: (u16, u16),
+    display: Option<Display>,
+    must_clear: bool,
+    /// Shown under the screen instead of the title, as printing would break the screen.
+    message: Option<String>,
+    recording: Recording,
+    /// Printed once the terminal is restored, it wouldn't be seen on the alternate screen.
+    error: Option<io::Error>,
+}
+
+impl TerminalInterface {
+    pub fn new(settings: EngineSettings) -> Result<TerminalInterface, String> {
+        let error = |error: io::Error| format!("cannot use the terminal: {}", error);
+
+        terminal::enable_raw_mode().map_err(error)?;
+        // built right away so that dropping it on the errors below restores the terminal
+        let mut interface = TerminalInterface {
+            stdout: io::stdout(),
+            settings,
+            is_running: true,
+            has_key_releases: false,
+            held_keys: [0; 16],
+            size: (0, 0),
+            display: None,
+            must_clear: true,
+            message: None,
+            recording: Recording::default(),
+            error: None,
+        };
+        interface.size = terminal::size().map_err(error)?;
+
+        queue!(
+            interface.stdout,
+            terminal::EnterAlternateScreen,
+            terminal::SetTitle(&interface.settings.title),
+            cursor::Hide
+        )
+        .map_err(error)?;
+        if terminal::supports_keyboard_enhancement().unwrap_or(false) {
+            queue!(
+                interface.stdout,
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )
+            .map_err(error)?;
+            interface.has_key_releases = true;
+        }
+        interface.stdout.flush().map_err(error)?;
+
+        Ok(interface)
+    }
+
+    /// Stops the emulation on an error of the terminal, reported when it's restored.
+    fn check(&mut self, result: io::Result<()>) {
+        if let Err(error) = result {
+            self.is_running = false;
+            self.error.get_or_insert(error);
+        }
+    }
+
+    fn mode(&self, display: &Display) -> Mode {
+        // the last line is kept for the status
+        let (columns, lines) = (self.size.0 as usize, self.size.1.saturating_sub(1) as usize);
+        if display.width() <= columns && display.height() / 2 <= lines {
+            Mode::HalfBlocks
+        } else if display.width() / 2 <= columns && display.height() / 4 <= lines {
+            Mode::Braille
+        } else {
+            Mode::TooSmall
+        }
+    }
+
+    fn color(&self, planes: u8) -> Color {
+        let [r, g, b] = self.settings.palette.color(planes);
+        Color::Rgb { r, g, b }
+    }
+
+    fn render(&mut self) -> io::Result<()> {
+        let display = match self.display.take() {
+            Some(display) => display,
+            None => return Ok(()),
+        };
+        let mode = self.mode(&display);
+
+        if self.must_clear {
+            queue!(self.stdout, ResetColor, terminal::Clear(ClearType::All))?;
+            self.must_clear = false;
+        }
+
+        let (cell_width, cell_height) = match mode {
+            Mode::HalfBlocks => (1, 2),
+            Mode::Braille => (2, 4),
+            Mode::TooSmall => {
+                let message = format!(
+                    "The terminal is too small, {}x{} characters are needed.",
+                    display.width() / 2,
+                    display.height() / 4 + 1
+                );
+                queue!(self.stdout, cursor::MoveTo(0, 0), Print(message))?;
+                self.display = Some(display);
+                return self.stdout.flush();
+            }
+        };
+
+        let (columns, lines) = (display.width() / cell_width, display.height() / cell_height);
+        // centered, the status line under the screen
+        let left = (self.size.0 as usize - columns) / 2;
+        let top = (self.size.1 as usize - 1 - lines) / 2;
+        let mut colors = None;
+
+        for line in 0..lines {
+            queue!(
+                self.stdout,
+                cursor::MoveTo(left as u16, (top + line) as u16)
+            )?;
+            for column in 0..columns {
+                let (x, y) = (column * cell_width, line * cell_height);
+                let (character, foreground, background) = match mode {
+                    Mode::HalfBlocks => (
+                        '▀',
+                        self.color(display.pixel(x, y)),
+                        self.color(display.pixel(x, y + 1)),
+                    ),
+                    _ => {
+                        let (dots, planes) = braille(&display, x, y);
+                        (dots, self.color(planes), self.color(0))
+                    }
+                };
+
+                if colors != Some((foreground, background)) {
+                    queue!(
+                        self.stdout,
+                        SetForegroundColor(foreground),
+                        SetBackgroundColor(background)
+                    )?;
+                    colors = Some((foreground, background));
+                }
+                queue!(self.stdout, Print(character))?;
+            }
+        }
+
+        let mut status = match self.message {
+            Some(ref message) => message.clone(),
+            None => format!("{} - Esc to quit", self.settings.title),
+        };
+        if self.recording.is_recording() {
+            status.insert_str(0, "[REC] ");
+        }
+        queue!(
+            self.stdout,
+            ResetColor,
+            cursor::MoveTo(left as u16, (top + lines) as u16),
+            Print(status),
+            terminal::Clear(ClearType::UntilNewLine)
+        )?;
+
+        self.display = Some(display);
+        self.stdout.flush()
+    }
+
+    fn press(&mut self, keypad: &mut [bool; 16], event: KeyEvent) {
+        let is_ctrl_c =
+            event.code == KeyCode::Char('c') && event.modifiers.contains(KeyModifiers::CONTROL);
+        if event.code == KeyCode::Esc || is_ctrl_c {
+            self.is_running = false;
+            return;
+        }
+
+        if event.kind == KeyEventKind::Press {
+            match event.code {
+                KeyCode::F(12) => return self.screenshot(),
+                KeyCode::F(10) => {
+                    self.message = Some(self.recording.toggle(&self.settings));
+                    let result = self.render();
+                    return self.check(result);
+                }
+                KeyCode::F(9) => {
+                    self.settings.palette = self.settings.palette.next();
+                    self.message =
+                        Some(format!("Palette: {}.", self.settings.palette.description()));
+                    let result = self.render();
+                    return self.check(result);
+                }
+                _ => {}
+            }
+        }
+
+        let key = match event.code {
+            KeyCode::Char(c) => match self.settings.keymap.key(c) {
+                Some(key) => key as usize,
+                None => return,
+            },
+            _ => return,
+        };
+
+        match event.kind {
+            KeyEventKind::Release => keypad[key] = false,
+            _ if self.has_key_releases => keypad[key] = true,
+            _ => {
+                keypad[key] = true;
+                self.held_keys[key] = KEY_HOLD_FRAMES;
+            }
+        }
+    }
+
+    fn screenshot(&mut self) {
+        let display = match self.display {
+            Some(ref display) => display,
+            None => return,
+        };
+        let path = screenshot::next_path(&self.settings.name, "png");
+        let result = screenshot::save(display, &self.settings.palette, self.settings.scale, &path);
+
+        self.message = Some(match result {
+            Ok(()) => format!("Saved the screenshot '{}'.", path.display()),
+            Err(error) => format!("error: {}", error),
+        });
+        let result = self.render();
+        self.check(result);
+    }
+}
+
+/// The braille pattern of the 2x4 pixels from (x, y) and the planes lit in them.
+fn braille(display: &Display, x: usize, y: usize) -> (char, u8) {
+    // bit of each dot, by column then line
+    const DOTS: [[u32; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+    let mut pattern = 0;
+    let mut planes = 0;
+
+    for (dx, column) in DOTS.iter().enumerate() {
+        for (dy, dot) in column.iter().enumerate() {
+            let pixel = display.pixel(x + dx, y + dy);
+            if pixel != 0 {
+                pattern |= dot;
+                planes |= pixel;
+            }
+        }
+    }
+
+    (std::char::from_u32(0x2800 + pattern).unwrap(), planes)
+}
+
+impl GraphicEngine for TerminalInterface {
+    fn draw(&mut self, display: &Display) {
+        self.display = Some(display.clone());
+        let result = self.render();
+        self.check(result);
+    }
+
+    fn flush(&mut self, keypad: &mut [bool; 16]) {
+        if let Some(ref display) = self.display {
+            if let Err(error) = self.recording.add_frame(display) {
+                self.message = Some(format!("error: {}", error));
+            }
+        }
+
+        if !self.has_key_releases {
+            for (key, frames) in self.held_keys.iter_mut().enumerate() {
+                if *frames > 0 {
+                    *frames -= 1;
+                    if *frames == 0 {
+                        keypad[key] = false;
+                    }
+                }
+            }
+        }
+
+        while self.is_running {
+            let event = match event::poll(Duration::from_secs(0)) {
+                Ok(true) => event::read(),
+                Ok(false) => break,
+                Err(error) => Err(error),
+            };
+            match event {
+                Ok(Event::Key(event)) => self.press(keypad, event),
+                Ok(Event::Resize(columns, lines)) => {
+                    self.size = (columns, lines);
+                    self.must_clear = true;
+                    let result = self.render();
+                    self.check(result);
+                }
+                Ok(_) => {}
+                Err(error) => self.check(Err(error)),
+            }
+        }
+    }
+
+    fn is_running(&self) -> bool {
+        self.is_running
+    }
+
+    fn init_draw(&mut self) {
+        self.display = Some(Display::new());
+        let result = self.render();
+        self.check(result);
+    }
+}
+
+impl Drop for TerminalInterface {
+    /// Gives the terminal back as it was, even when the emulation panics.
+    fn drop(&mut self) {
+        if self.has_key_releases {
+            let _ = queue!(self.stdout, PopKeyboardEnhancementFlags);
+        }
+        let _ = queue!(
+            self.stdout,
+            ResetColor,
+            cursor::Show,
+            terminal::LeaveAlternateScreen
+        );
+        let _ = self.stdout.flush();
+        let _ = terminal::disable_raw_mode();
+
+        if let Some(ref error) = self.error {
+            eprintln!("error: cannot use the terminal: {}", error);
+        }
+    }
+}
@@ -0,0 +1,439 @@
+use super::disassembler;
+
+/// One line of a trace: the machine state before an instruction is executed.
+pub struct TraceLine {
+    pub line: usize,
+    pub cycle: Option<u64>,
+    pub pc: usize,
+    pub opcode: u16,
+    pub v: [u8; 16],
+    pub i: usize,
+}
+
+/// Parses the trace format written by `Tracer`: `cycle pc opcode v0 ... vf i`.
+///
+/// Other emulators decorate the same fields in many ways, so some variations are accepted:
+/// the cycle column may be missing, values may be prefixed by `$` or `0x`,
+/// and fields may be named (`PC:0200`, `v3=1F`...), in which case their order doesn't matter.
+/// Empty lines and lines starting with `#` or `//` are ignored.
+pub fn parse(text: &str) -> Result<Vec<TraceLine>, String> {
+    let mut lines = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("//") {
+            continue;
+        }
+
+        match parse_line(line, index + 1) {
+            Some(trace_line) => lines.push(trace_line),
+            None => return Err(format!("line {}: cannot read '{}'", index + 1, line)),
+        }
+    }
+
+    Ok(lines)
+}
+
+fn parse_hex(value: &str) -> Option<usize> {
+    let value = value.trim_start_matches('$');
+    let value = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .unwrap_or(value);
+    usize::from_str_radix(value, 16).ok()
+}
+
+fn parse_line(line: &str, number: usize) -> Option<TraceLine> {
+    let fields: Vec<&str> = line
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|field| !field.is_empty())
+        .collect();
+
+    let mut trace_line = TraceLine {
+        line: number,
+        cycle: None,
+        pc: 0,
+        opcode: 0,
+        v: [0; 16],
+        i: 0,
+    };
+
+    if fields
+        .iter()
+        .any(|field| field.contains(':') || field.contains('='))
+    {
+        let mut found = 0;
+        for field in fields {
+            let mut parts = field.splitn(2, [':', '=']);
+            let (name, value) = match (parts.next(), parts.next()) {
+                (Some(name), Some(value)) => (name.to_lowercase(), value),
+                _ => continue,
+            };
+            match name.as_str() {
+                "cycle" | "cycles" => trace_line.cycle = Some(value.parse().ok()?),
+                "pc" => trace_line.pc = parse_hex(value)?,
+                "op" | "opcode" => trace_line.opcode = parse_hex(value)? as u16,
+                "i" => trace_line.i = parse_hex(value)?,
+                _ => match name.strip_prefix('v').and_then(parse_hex) {
+                    Some(register) if register < 16 => {
+                        trace_line.v[register] = parse_hex(value)? as u8
+                    }
+                    _ => continue,
+                },
+            }
+            found += 1;
+        }
+        // pc, opcode, 16 registers and I
+        return if found >= 19 { Some(trace_line) } else { None };
+    }
+
+    let fields = match fields.len() {
+        19 => &fields[..],
+        n if n >= 20 => {
+            trace_line.cycle = Some(fields[0].parse().ok()?);
+            &fields[1..]
+        }
+        _ => return None,
+    };
+
+    trace_line.pc = parse_hex(fields[0])?;
+    trace_line.opcode = parse_hex(fields[1])? as u16;
+    for (register, field) in fields[2..18].iter().enumerate() {
+        trace_line.v[register] = parse_hex(field)? as u8;
+    }
+    trace_line.i = parse_hex(fields[18])?;
+
+    Some(trace_line)
+}
+
+impl TraceLine {
+    /// `(field, ours, reference)` for every field that differs.
+    fn differences(&self, reference: &TraceLine) -> Vec<(String, String, String)> {
+        let mut differences = Vec::new();
+
+        if self.pc != reference.pc {
+            differences.push((
+                "PC".to_string(),
+                format!("{:04X}", self.pc),
+                format!("{:04X}", reference.pc),
+            ));
+        }
+        if self.opcode != reference.opcode {
+            differences.push((
+                "opcode".to_string(),
+                format!("{:04X}", self.opcode),
+                format!("{:04X}", reference.opcode),
+            ));
+        }
+        for register in 0..16 {
+            if self.v[register] != reference.v[register] {
+                differences.push((
+                    format!("V{:X}", register),
+                    format!("{:02X}", self.v[register]),
+                    format!("{:02X}", reference.v[register]),
+                ));
+            }
+        }
+        if self.i != reference.i {
+            differences.push((
+                "I".to_string(),
+                format!("{:04X}", self.i),
+                format!("{:04X}", reference.i),
+            ));
+        }
+
+        differences
+    }
+
+    fn row(&self, label: &str) -> String {
+        format!(
+            "  {:<9}  {}  {}\n",
+            label,
+            self.describe(),
+            self.registers()
+        )
+    }
+
+    fn describe(&self) -> String {
+        let mnemonic = disassembler::mnemonic(self.opcode).unwrap_or_else(|| "???".to_string());
+        format!("${:03X}: {:04X}  {:<18}", self.pc, self.opcode, mnemonic)
+    }
+
+    fn registers(&self) -> String {
+        let registers: Vec<String> = self.v.iter().map(|v| format!("{:02X}", v)).collect();
+        format!("{}  I={:04X}", registers.join(" "), self.i)
+    }
+}
+
+/// The first instruction where the traces differ, cycle counts aside since every emulator
+/// counts them its own way. `None` if a trace is the beginning of the other.
+pub fn first_divergence(ours: &[TraceLine], reference: &[TraceLine]) -> Option<usize> {
+    ours.iter()
+        .zip(reference)
+        .position(|(ours, reference)| !ours.differences(reference).is_empty())
+}
+
+/// Our program run again up to the divergent instruction.
+pub enum Replay<'a> {
+    /// The memory, the replay having the registers of our trace.
+    Memory(&'a [u8]),
+    /// The registers of a replay which went another way, its memory would be misleading.
+    Diverged(String),
+}
+
+/// Describes the first divergence with the `context` instructions preceding it,
+/// and with the memory around PC and I when the program was replayed up to it.
+/// `None` if the traces are identical.
+pub fn report(
+    ours: &[TraceLine],
+    reference: &[TraceLine],
+    context: usize,
+    replay: Option<Replay>,
+) -> Option<String> {
+    let index = match first_divergence(ours, reference) {
+        Some(index) => index,
+        None if ours.len() == reference.len() => return None,
+        None => {
+            let (longer, name) = if ours.len() > reference.len() {
+                (ours, "our trace")
+            } else {
+                (reference, "the reference trace")
+            };
+            let common = ours.len().min(reference.len());
+            return Some(format!(
+                "The traces agree on {} instructions, then only {} continues with {}\n",
+                common,
+                name,
+                longer[common].describe()
+            ));
+        }
+    };
+
+    let mut text = format!(
+        "First divergence at instruction {} (our line {}, reference line {})\n\n",
+        index, ours[index].line, reference[index].line
+    );
+
+    let start = index.saturating_sub(context);
+    if start < index {
+        text.push_str("Preceding instructions (identical in both traces):\n");
+        for line in &ours[start..index] {
+            text.push_str(&line.row(""));
+        }
+        text.push('\n');
+    }
+
+    text.push_str(&format!(
+        "{:45}V0 V1 V2 V3 V4 V5 V6 V7 V8 V9 VA VB VC VD VE VF\n",
+        ""
+    ));
+    text.push_str(&ours[index].row("ours"));
+    text.push_str(&reference[index].row("reference"));
+    text.push('\n');
+
+    text.push_str("Differences:\n");
+    for (field, ours, reference) in ours[index].differences(&reference[index]) {
+        text.push_str(&format!(
+            "  {:<6} ours {:>4}  reference {:>4}\n",
+            field, ours, reference
+        ));
+    }
+
+    match replay {
+        Some(Replay::Memory(memory)) => {
+            // the memory of the reference may already differ, the traces can't tell
+            text.push_str("\nOur memory before the divergent instruction:\n");
+            text.push_str(&memory_rows(memory, "PC", ours[index].pc));
+            text.push_str(&memory_rows(memory, "I", ours[index].i));
+        }
+        Some(Replay::Diverged(registers)) => text.push_str(&format!(
+            "\nThe replay left our trace before the divergence, with {}, \
+             so its memory isn't shown\n",
+            registers
+        )),
+        None => {}
+    }
+
+    if index > 0 {
+        text.push_str(&format!(
+            "\nThe last instruction executed before the divergence is {}\n",
+            ours[index - 1].describe().trim_end()
+        ));
+    }
+
+    Some(text)
+}
+
+/// The two lines of 16 bytes from the one holding `address`, wrapping around the memory.
+fn memory_rows(memory: &[u8], name: &str, address: usize) -> String {
+    let start = address & !0xF;
+    (0..2)
+        .map(|row| {
+            let row_start = start + row * 16;
+            let bytes: Vec<String> = (row_start..row_start + 16)
+                .map(|address| format!("{:02X}", memory[address % memory.len()]))
+                .collect();
+            let label = if row == 0 { name } else { "" };
+            format!(
+                "  {:<3} {:04X}: {}\n",
+                label,
+                row_start % memory.len(),
+                bytes.join(" ")
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A trace line in the format of `Tracer`, the registers from V0 on.
+    fn line(cycle: u64, pc: usize, opcode: u16, v: &[u8], i: usize) -> String {
+        let mut registers = [0; 16];
+        registers[..v.len()].copy_from_slice(v);
+        let registers: Vec<String> = registers.iter().map(|v| format!("{:02X}", v)).collect();
+        format!(
+            "{} {:04X} {:04X} {} {:04X}\n",
+            cycle,
+            pc,
+            opcode,
+            registers.join(" "),
+            i
+        )
+    }
+
+    fn ours() -> Vec<TraceLine> {
+        let text = [
+            line(0, 0x200, 0x6005, &[], 0),
+            line(1, 0x202, 0x7001, &[5], 0),
+            line(2, 0x204, 0xA300, &[6], 0),
+        ]
+        .concat();
+        parse(&text).unwrap()
+    }
+
+    #[test]
+    fn parse_formats() {
+        let trace = ours();
+        assert_eq!(trace.len(), 3);
+        assert_eq!(trace[1].cycle, Some(1));
+        assert_eq!((trace[1].pc, trace[1].opcode), (0x202, 0x7001));
+        assert_eq!(trace[2].v[0], 6);
+
+        let text = "# another emulator\n\
+            \n\
+            $0200 0x6005 00 01 02 03 04 05 06 07 08 09 0A 0B 0C 0D 0E 0F $0300\n\
+            // named fields, in any order\n\
+            I:0300 PC:0202 op=7001 v0=05 v1=01 v2=02 v3=03 v4=04 v5=05 v6=06 v7=07 \
+            v8=08 v9=09 va=0A vb=0B vc=0C vd=0D ve=0E vf=0F cycle=7\n";
+        let trace = parse(text).unwrap();
+        assert_eq!(trace.len(), 2);
+        assert_eq!(trace[0].line, 3);
+        assert_eq!(trace[0].cycle, None);
+        assert_eq!(
+            (trace[0].pc, trace[0].opcode, trace[0].i),
+            (0x200, 0x6005, 0x300)
+        );
+        assert_eq!(trace[0].v[0xF], 0x0F);
+        assert_eq!(trace[1].line, 5);
+        assert_eq!(trace[1].cycle, Some(7));
+        assert_eq!(
+            (trace[1].pc, trace[1].opcode, trace[1].i),
+            (0x202, 0x7001, 0x300)
+        );
+        assert_eq!(trace[1].v[0xA], 0x0A);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(
+            parse("0 0200 6005\n").err().unwrap(),
+            "line 1: cannot read '0 0200 6005'"
+        );
+        let text = format!("{}PC:0202 op=7001\n", line(0, 0x200, 0x6005, &[], 0));
+        assert_eq!(
+            parse(&text).err().unwrap(),
+            "line 2: cannot read 'PC:0202 op=7001'"
+        );
+    }
+
+    #[test]
+    fn identical_traces() {
+        assert!(first_divergence(&ours(), &ours()).is_none());
+        assert!(report(&ours(), &ours(), 10, None).is_none());
+
+        // the cycles are counted in other ways by other emulators
+        let mut reference = ours();
+        reference[2].cycle = Some(20);
+        assert!(report(&ours(), &reference, 10, None).is_none());
+    }
+
+    #[test]
+    fn shorter_trace() {
+        let mut reference = ours();
+        reference.pop();
+        assert_eq!(
+            report(&ours(), &reference, 10, None).unwrap(),
+            "The traces agree on 2 instructions, then only our trace continues with \
+             $204: A300  LD I, 0x300       \n"
+        );
+    }
+
+    #[test]
+    fn divergence() {
+        let text = [
+            "# reference\n".to_string(),
+            line(0, 0x200, 0x6005, &[], 0),
+            line(1, 0x202, 0x7001, &[5], 0),
+            line(2, 0x204, 0xA300, &[7], 0),
+        ]
+        .concat();
+        let reference = parse(&text).unwrap();
+        assert_eq!(first_divergence(&ours(), &reference), Some(2));
+
+        let mut memory = vec![0; 0x1000];
+        memory[0x200..0x206].copy_from_slice(&[0x60, 0x05, 0x70, 0x01, 0xA3, 0x00]);
+        let report = report(&ours(), &reference, 1, Some(Replay::Memory(&memory))).unwrap();
+        assert_eq!(
+            report,
+            "First divergence at instruction 2 (our line 3, reference line 4)\n\
+            \n\
+            Preceding instructions (identical in both traces):\n             \
+            $202: 7001  ADD V0, 0x01        05 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00  I=0000\n\
+            \n                                             \
+            V0 V1 V2 V3 V4 V5 V6 V7 V8 V9 VA VB VC VD VE VF\n  \
+            ours       $204: A300  LD I, 0x300         06 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00  I=0000\n  \
+            reference  $204: A300  LD I, 0x300         07 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00  I=0000\n\
+            \n\
+            Differences:\n  \
+            V0     ours   06  reference   07\n\
+            \n\
+            Our memory before the divergent instruction:\n  \
+            PC  0200: 60 05 70 01 A3 00 00 00 00 00 00 00 00 00 00 00\n      \
+            0210: 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00\n  \
+            I   0000: 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00\n      \
+            0010: 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00\n\
+            \n\
+            The last instruction executed before the divergence is $202: 7001  ADD V0, 0x01\n"
+        );
+    }
+
+    #[test]
+    fn diverged_replay() {
+        let mut reference = ours();
+        reference[2].v[0] = 7;
+        let report = report(
+            &ours(),
+            &reference,
+            0,
+            Some(Replay::Diverged("PC 0206".to_string())),
+        )
+        .unwrap();
+        assert!(!report.contains("Our memory"));
+        assert!(report.contains(
+            "The replay left our trace before the divergence, with PC 0206, \
+             so its memory isn't shown\n"
+        ));
+    }
+}
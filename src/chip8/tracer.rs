@@ -0,0 +1,204 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+/// Verbosity of the trace, each level includes the previous ones.
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug)]
+pub enum Level {
+    Off,
+    /// Rare events: screen cleared, key awaited, sound started.
+    Info,
+    /// Every draw, key query and timer write.
+    Debug,
+    /// Every executed instruction.
+    Trace,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Category {
+    Cpu,
+    Draw,
+    Input,
+    Timers,
+}
+
+const CATEGORY_COUNT: usize = 4;
+
+impl Category {
+    fn name(self) -> &'static str {
+        match self {
+            Category::Cpu => "cpu",
+            Category::Draw => "draw",
+            Category::Input => "input",
+            Category::Timers => "timers",
+        }
+    }
+}
+
+/// Filters and writes the execution trace.
+///
+/// Events are written on stderr. Executed instructions go to the trace file if there is one,
+/// one line per instruction: `cycle pc opcode v0 ... vf i`, in hexadecimal except the cycle.
+/// That's the plain format most CHIP-8 emulators can print with a single `printf`,
+/// which makes our traces easy to compare with theirs.
+pub struct Tracer {
+    level: Level,
+    categories: [bool; CATEGORY_COUNT],
+    addresses: Option<(usize, usize)>,
+    file: Option<BufWriter<File>>,
+    /// Built from a filter, rather than off by default.
+    has_filter: bool,
+}
+
+impl Default for Tracer {
+    fn default() -> Tracer {
+        Tracer::new()
+    }
+}
+
+impl Tracer {
+    pub fn new() -> Tracer {
+        Tracer {
+            level: Level::Off,
+            categories: [true; CATEGORY_COUNT],
+            addresses: None,
+            file: None,
+            has_filter: false,
+        }
+    }
+
+    /// Builds a tracer from a comma separated filter like `trace,cpu,draw,0x200-0x2FF`.
+    ///
+    /// * a level among `off`, `info`, `debug` and `trace` (`info` if omitted)
+    /// * categories among `cpu`, `draw`, `input` and `timers` (all of them if omitted)
+    /// * an inclusive address range, only events happening there are kept
+    pub fn parse(filter: &str) -> Result<Tracer, String> {
+        let mut tracer = Tracer::new();
+        let mut categories = Vec::new();
+
+        tracer.level = Level::Info;
+        tracer.has_filter = true;
+
+        for item in filter
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+        {
+            match item {
+                "off" => tracer.level = Level::Off,
+                "info" => tracer.level = Level::Info,
+                "debug" => tracer.level = Level::Debug,
+                "trace" => tracer.level = Level::Trace,
+                "cpu" => categories.push(Category::Cpu),
+                "draw" => categories.push(Category::Draw),
+                "input" => categories.push(Category::Input),
+                "timers" => categories.push(Category::Timers),
+                _ => tracer.addresses = Some(parse_range(item)?),
+            }
+        }
+
+        if !categories.is_empty() {
+            tracer.categories = [false; CATEGORY_COUNT];
+            for category in categories {
+                tracer.categories[category as usize] = true;
+            }
+        }
+
+        Ok(tracer)
+    }
+
+    /// Reads the filter from `CHIP8_TRACE` and the trace file path from `CHIP8_TRACE_FILE`.
+    pub fn from_env() -> Result<Tracer, String> {
+        let mut tracer = match std::env::var("CHIP8_TRACE") {
+            Ok(filter) => Tracer::parse(&filter)?,
+            Err(_) => Tracer::new(),
+        };
+
+        if let Ok(path) = std::env::var("CHIP8_TRACE_FILE") {
+            tracer.set_file(std::path::Path::new(&path))?;
+        }
+
+        Ok(tracer)
+    }
+
+    /// Without a filter, the file gets every instruction, as with `trace,cpu`.
+    pub fn set_file(&mut self, path: &std::path::Path) -> Result<(), String> {
+        let file = File::create(path)
+            .map_err(|error| format!("cannot create '{}': {}", path.display(), error))?;
+        self.file = Some(BufWriter::new(file));
+
+        if !self.has_filter {
+            self.level = Level::Trace;
+            self.categories = [false; CATEGORY_COUNT];
+            self.categories[Category::Cpu as usize] = true;
+        }
+        Ok(())
+    }
+
+    pub fn is_enabled(&self, level: Level, category: Category, pc: usize) -> bool {
+        level <= self.level
+            && self.categories[category as usize]
+            && match self.addresses {
+                Some((start, end)) => start <= pc && pc <= end,
+                None => true,
+            }
+    }
+
+    /// Writes an event, the message is only built if the event passes the filters.
+    pub fn log<F: FnOnce() -> String>(
+        &mut self,
+        level: Level,
+        category: Category,
+        pc: usize,
+        message: F,
+    ) {
+        if self.is_enabled(level, category, pc) {
+            eprintln!("{:?} {} ${:03X}: {}", level, category.name(), pc, message());
+        }
+    }
+
+    /// Writes the state of the machine before an instruction is executed.
+    pub fn instruction(&mut self, cycle: u64, pc: usize, opcode: u16, v: &[u8], i: usize) {
+        if !self.is_enabled(Level::Trace, Category::Cpu, pc) {
+            return;
+        }
+
+        let mut line = format!("{} {:04X} {:04X}", cycle, pc, opcode);
+        for register in v {
+            line.push_str(&format!(" {:02X}", register));
+        }
+        line.push_str(&format!(" {:04X}", i));
+
+        let result = match self.file {
+            Some(ref mut file) => writeln!(file, "{}", line),
+            None => {
+                eprintln!("{}", line);
+                Ok(())
+            }
+        };
+        // like a full disk, a closed pipe stops the tracing but not the emulation
+        if let Err(error) = result {
+            eprintln!("error: cannot write the trace: {}", error);
+            self.level = Level::Off;
+            self.file = None;
+        }
+    }
+}
+
+fn parse_range(item: &str) -> Result<(usize, usize), String> {
+    let parse_address = |address: &str| {
+        let address = address.trim();
+        match address.strip_prefix("0x") {
+            Some(hex) => usize::from_str_radix(hex, 16).ok(),
+            None => address.parse().ok(),
+        }
+    };
+
+    let mut bounds = item.splitn(2, '-');
+    match (
+        bounds.next().and_then(parse_address),
+        bounds.next().and_then(parse_address),
+    ) {
+        (Some(start), Some(end)) if start <= end => Ok((start, end)),
+        _ => Err(format!("unknown trace filter '{}'", item)),
+    }
+}
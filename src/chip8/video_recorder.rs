@@ -0,0 +1,141 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use super::display::Display;
+use super::graphic_engine::Palette;
+use super::screenshot;
+use super::FREQUENCY;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum VideoFormat {
+    /// YUV4MPEG2 in 4:4:4, the size and frame rate are in the header.
+    Y4m,
+    /// RGB24 frames after a line `RGB24 W<width> H<height> F<fps>`, like
+    /// `tail -n +2 | ffmpeg -f rawvideo -pix_fmt rgb24 -s <width>x<height> -r <fps> -i -`
+    /// reads them.
+    Rgb24,
+}
+
+impl FromStr for VideoFormat {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<VideoFormat, String> {
+        match text.to_lowercase().as_str() {
+            "y4m" => Ok(VideoFormat::Y4m),
+            "rgb24" | "rgb" => Ok(VideoFormat::Rgb24),
+            _ => Err(format!("unknown video format '{}', try y4m or rgb24", text)),
+        }
+    }
+}
+
+/// Writes every frame uncompressed at 60 frames per second, for an external encoder.
+pub struct VideoRecorder {
+    writer: BufWriter<Box<dyn Write + Send>>,
+    /// `-` for the standard output.
+    path: PathBuf,
+    format: VideoFormat,
+    palette: Palette,
+    scale: u32,
+    width: usize,
+    height: usize,
+}
+
+impl VideoRecorder {
+    /// The frames are big enough for both resolutions at `scale`.
+    pub fn create(
+        path: &Path,
+        format: VideoFormat,
+        palette: &Palette,
+        scale: u32,
+    ) -> Result<VideoRecorder, String> {
+        let output: Box<dyn Write + Send> = if path == Path::new("-") {
+            Box::new(io::stdout())
+        } else {
+            Box::new(
+                File::create(path)
+                    .map_err(|error| format!("cannot create '{}': {}", path.display(), error))?,
+            )
+        };
+        let (width, height) = screenshot::canvas_size(scale);
+
+        let mut recorder = VideoRecorder {
+            writer: BufWriter::new(output),
+            path: path.to_path_buf(),
+            format,
+            palette: *palette,
+            scale,
+            width,
+            height,
+        };
+
+        let header = match format {
+            VideoFormat::Y4m => format!(
+                "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444\n",
+                width, height, FREQUENCY
+            ),
+            VideoFormat::Rgb24 => format!("RGB24 W{} H{} F{}\n", width, height, FREQUENCY),
+        };
+        recorder.write(header.as_bytes())?;
+        Ok(recorder)
+    }
+
+    pub fn is_stdout(&self) -> bool {
+        self.path == Path::new("-")
+    }
+
+    /// Adds the display shown during one frame.
+    pub fn add_frame(&mut self, display: &Display) -> Result<(), String> {
+        let planes = screenshot::canvas(display, self.scale, self.width, self.height);
+
+        let frame = match self.format {
+            VideoFormat::Y4m => {
+                // the Y, Cb and Cr values of each palette colour
+                let mut components = [[0u8; 4]; 3];
+                for (index, &rgb) in self.palette.colors.iter().enumerate() {
+                    for (component, &value) in ycbcr(rgb).iter().enumerate() {
+                        components[component][index] = value;
+                    }
+                }
+
+                // every component is written whole: Y, then Cb, then Cr
+                let mut frame = b"FRAME\n".to_vec();
+                for values in components.iter() {
+                    frame.extend(planes.iter().map(|&plane| values[plane as usize]));
+                }
+                frame
+            }
+            VideoFormat::Rgb24 => planes
+                .iter()
+                .flat_map(|&plane| self.palette.color(plane).to_vec())
+                .collect(),
+        };
+
+        self.write(&frame)
+    }
+
+    pub fn finish(mut self) -> Result<(), String> {
+        let path = self.path.clone();
+        self.writer
+            .flush()
+            .map_err(|error| format!("cannot write '{}': {}", path.display(), error))
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), String> {
+        let path = &self.path;
+        self.writer
+            .write_all(bytes)
+            .map_err(|error| format!("cannot write '{}': {}", path.display(), error))
+    }
+}
+
+/// BT.601 in limited range, what encoders assume for Y4M.
+fn ycbcr([r, g, b]: [u8; 3]) -> [u8; 3] {
+    let (r, g, b) = (r as i32, g as i32, b as i32);
+    [
+        (16 + ((66 * r + 129 * g + 25 * b + 128) >> 8)) as u8,
+        (128 + ((-38 * r - 74 * g + 112 * b + 128) >> 8)) as u8,
+        (128 + ((112 * r - 94 * g - 18 * b + 128) >> 8)) as u8,
+    ]
+}
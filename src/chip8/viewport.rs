@@ -0,0 +1,80 @@
+use std::str::FromStr;
+
+use super::display::{HIRES_HEIGHT, HIRES_WIDTH};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Scaling {
+    /// Every pixel is the same whole number of screen pixels, the sharpest.
+    Integer,
+    /// The display fills the window as much as possible.
+    Fractional,
+}
+
+impl FromStr for Scaling {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Scaling, String> {
+        match text.to_lowercase().as_str() {
+            "integer" => Ok(Scaling::Integer),
+            "fractional" => Ok(Scaling::Fractional),
+            _ => Err(format!(
+                "unknown scaling '{}', try integer or fractional",
+                text
+            )),
+        }
+    }
+}
+
+/// Where the display goes in the window, centred with bars around it
+/// where the window doesn't have the display's aspect ratio.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Viewport {
+    pub x: f64,
+    pub y: f64,
+    /// Size of a pixel of the display, in window pixels.
+    pub pixel_size: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Viewport {
+    /// The viewport of a display of `width` x `height` pixels in a window.
+    ///
+    /// With integer scaling, the size is chosen for the hires display when it can,
+    /// so that the picture keeps its size when a program switches resolution.
+    pub fn new(window: (f64, f64), width: usize, height: usize, scaling: Scaling) -> Viewport {
+        let (width, height) = (width as f64, height as f64);
+        let fit = |width: f64, height: f64| (window.0 / width).min(window.1 / height);
+
+        let pixel_size = match scaling {
+            Scaling::Fractional => fit(width, height),
+            Scaling::Integer => {
+                let (hires_width, hires_height) = (HIRES_WIDTH as f64, HIRES_HEIGHT as f64);
+                let hires_size = fit(hires_width, hires_height).floor();
+                if hires_size >= 1. {
+                    hires_size * hires_width / width
+                } else {
+                    fit(width, height).floor().max(1.)
+                }
+            }
+        };
+
+        Viewport {
+            x: ((window.0 - width * pixel_size) / 2.).floor(),
+            y: ((window.1 - height * pixel_size) / 2.).floor(),
+            pixel_size,
+            width: width * pixel_size,
+            height: height * pixel_size,
+        }
+    }
+
+    /// The window area of the pixel (x, y) of the display, as `[x, y, width, height]`.
+    /// The edges are rounded so that the pixels neither overlap nor leave gaps.
+    pub fn pixel_rect(&self, x: usize, y: usize) -> [f64; 4] {
+        let left = (self.x + x as f64 * self.pixel_size).floor();
+        let top = (self.y + y as f64 * self.pixel_size).floor();
+        let right = (self.x + (x + 1) as f64 * self.pixel_size).floor();
+        let bottom = (self.y + (y + 1) as f64 * self.pixel_size).floor();
+        [left, top, right - left, bottom - top]
+    }
+}
@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use clap::Args;
+use serde::{Deserialize, Deserializer};
+
+use chip_huit::chip8::database::{rom_hash, RomInfo};
+use chip_huit::chip8::frontends::Frontend;
+use chip_huit::chip8::graphic_engine::{Keymap, Palette, Renderer};
+use chip_huit::chip8::persistence::Persistence;
+use chip_huit::chip8::quirks::{Platform, Quirks};
+use chip_huit::chip8::viewport::Scaling;
+
+/// What can be set on the command line, in the config file and for a single ROM.
+///
+/// ```toml
+/// backend = "sdl"
+/// palette = "000000,FFB000"
+///
+/// # Settings of the ROM whose SHA-1 is 2b5ee5...
+/// [rom.2b5ee5...]
+/// platform = "schip"
+/// speed = 30
+/// ```
+#[derive(Args, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    /// Where the emulation is shown: piston, sdl, software, terminal or headless,
+    /// among the ones compiled in [default: the first of them which starts]
+    #[arg(short, long)]
+    #[serde(default, deserialize_with = "parse")]
+    pub backend: Option<Frontend>,
+    /// Instructions executed per frame, there are 60 frames per second [default: 10]
+    #[arg(short, long)]
+    pub speed: Option<u32>,
+    /// Size of a pixel on the screen when the window opens [default: 4]
+    #[arg(long)]
+    pub scale: Option<u32>,
+    /// How the display fills a resized window: integer or fractional [default: integer]
+    #[arg(long)]
+    #[serde(default, deserialize_with = "parse")]
+    pub scaling: Option<Scaling>,
+    /// classic, amber, green-phosphor, lcd, high-contrast, color-blind, or the background
+    /// and plane colours, 2 or 4 of them like `000000,FFFFFF` [default: classic]
+    #[arg(long)]
+    #[serde(default, deserialize_with = "parse")]
+    pub palette: Option<Palette>,
+    /// chip8, schip or xochip [default: guessed from the instructions]
+    #[arg(short, long)]
+    #[serde(default, deserialize_with = "parse")]
+    pub platform: Option<Platform>,
+    /// A platform preset and quirks to toggle, like `schip,-clipping,+display-wait`
+    /// [default: the preset of the platform]
+    #[arg(short, long)]
+    #[serde(default, deserialize_with = "parse")]
+    pub quirks: Option<Quirks>,
+    /// The 16 keyboard keys of the keypad keys 0 to F [default: x123qweasdzc4rfv]
+    #[arg(short, long)]
+    #[serde(default, deserialize_with = "parse")]
+    pub keymap: Option<Keymap>,
+    /// Against the flicker: `blend` or `phosphor`, and the part of the previous frame
+    /// kept like `phosphor:0.7` [default: none]
+    #[arg(long)]
+    #[serde(default, deserialize_with = "parse")]
+    pub persistence: Option<Persistence>,
+    /// What draws the SDL window: accelerated, or software for the machines
+    /// without GPU drivers [default: accelerated]
+    #[arg(long)]
+    #[serde(default, deserialize_with = "parse")]
+    pub renderer: Option<Renderer>,
+}
+
+impl Settings {
+    /// Takes the settings missing here from `other`.
+    pub fn or(self, other: Settings) -> Settings {
+        Settings {
+            backend: self.backend.or(other.backend),
+            speed: self.speed.or(other.speed),
+            scale: self.scale.or(other.scale),
+            scaling: self.scaling.or(other.scaling),
+            palette: self.palette.or(other.palette),
+            platform: self.platform.or(other.platform),
+            quirks: self.quirks.or(other.quirks),
+            keymap: self.keymap.or(other.keymap),
+            persistence: self.persistence.or(other.persistence),
+            renderer: self.renderer.or(other.renderer),
+        }
+    }
+}
+
+/// The known-good settings of the ROM database.
+impl From<&RomInfo> for Settings {
+    fn from(info: &RomInfo) -> Settings {
+        Settings {
+            backend: None,
+            speed: info.speed,
+            scale: None,
+            scaling: None,
+            palette: info.palette,
+            platform: info.platform,
+            quirks: info.quirks,
+            keymap: info.keymap,
+            persistence: None,
+            renderer: None,
+        }
+    }
+}
+
+/// The values are written like on the command line.
+fn parse<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr<Err = String>,
+{
+    let text = String::deserialize(deserializer)?;
+    text.parse().map(Some).map_err(serde::de::Error::custom)
+}
+
+#[derive(Default)]
+pub struct Config {
+    defaults: Settings,
+    /// Settings by SHA-1 of the ROM.
+    roms: HashMap<String, Settings>,
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/chip_huit/config.toml`, or its equivalent outside of Linux.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("chip_huit").join("config.toml"))
+    }
+
+    /// A missing file is an empty config, unless it was asked for explicitly.
+    pub fn load(path: &Path, is_explicit: bool) -> Result<Config, String> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound && !is_explicit => {
+                return Ok(Config::default())
+            }
+            Err(error) => return Err(format!("cannot read '{}': {}", path.display(), error)),
+        };
+
+        Config::parse(&text)
+            .map_err(|error| format!("{}: {}", path.display(), error.to_string().trim_end()))
+    }
+
+    /// The `[rom.<sha1>]` tables are split from the defaults by hand,
+    /// as serde ignores unknown fields next to a flattened struct.
+    fn parse(text: &str) -> Result<Config, toml::de::Error> {
+        let mut table: toml::Table = toml::from_str(text)?;
+        let roms: HashMap<String, Settings> = match table.remove("rom") {
+            Some(roms) => roms.try_into()?,
+            None => HashMap::new(),
+        };
+
+        Ok(Config {
+            defaults: table.try_into()?,
+            roms: roms
+                .into_iter()
+                .map(|(hash, settings)| (hash.to_lowercase(), settings))
+                .collect(),
+        })
+    }
+
+    /// The settings of the ROM, completed by the known-good settings
+    /// of the ROM database and then by the defaults.
+    pub fn settings(mut self, rom: &[u8], known: Settings) -> Settings {
+        let own = self.roms.remove(&rom_hash(rom)).unwrap_or_default();
+        own.or(known).or(self.defaults)
+    }
+}
@@ -0,0 +1,385 @@
+//! The C API, to embed the emulator in C and C++ programs.
+//! The header `include/chip_huit.h` is generated from this file by cbindgen, run again
+//! when the API changes.
+//!
+//! ```text
+//! cbindgen --config cbindgen.toml --output include/chip_huit.h
+//! cargo rustc --release --lib --crate-type cdylib --no-default-features --features ffi
+//! cc -Iinclude examples/embed.c -Ltarget/release -lchip_huit -o embed
+//! ```
+//!
+//! The functions returning an `int` give 0 on success and -1 on errors,
+//! described by `chip8_last_error` until the next call that fails.
+//! The emulators must come from `chip8_new`, and the buffers have the sizes given with them.
+//! A null emulator is an error, or gives 0, `false` or a null pointer, and a null buffer
+//! of size 0 is empty.
+
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+use std::slice;
+
+use crate::chip8::graphic_engine::Palette;
+use crate::chip8::headless_interface::HeadlessInterface;
+use crate::chip8::Chip8;
+
+/// An emulator with a program, driven by the host which calls `chip8_step_frame`
+/// 60 times per second.
+pub struct Chip8Emulator {
+    chip: Chip8<HeadlessInterface>,
+    palette: Palette,
+    /// The display in RGBA, as of the last call to `chip8_framebuffer`.
+    frame: Vec<u8>,
+    error: CString,
+}
+
+/// The error of the calls without emulator.
+const NULL_EMULATOR: &[u8] = b"the emulator is null\0";
+
+impl Chip8Emulator {
+    /// Keeps the error for `chip8_last_error`.
+    fn check(&mut self, result: Result<(), String>) -> c_int {
+        match result {
+            Ok(()) => 0,
+            Err(error) => {
+                self.error = CString::new(error.replace('\0', "")).unwrap_or_default();
+                -1
+            }
+        }
+    }
+}
+
+/// `size` bytes from `data`, which can be null when there are none.
+unsafe fn bytes<'a>(data: *const u8, size: usize) -> Result<&'a [u8], String> {
+    if !data.is_null() {
+        Ok(slice::from_raw_parts(data, size))
+    } else if size == 0 {
+        Ok(&[])
+    } else {
+        Err(format!("null buffer of {} bytes", size))
+    }
+}
+
+/// A new emulator without program, to free with `chip8_free`.
+#[no_mangle]
+pub extern "C" fn chip8_new() -> *mut Chip8Emulator {
+    Box::into_raw(Box::new(Chip8Emulator {
+        chip: Chip8::headless(),
+        palette: Palette::default(),
+        frame: Vec::new(),
+        error: CString::default(),
+    }))
+}
+
+/// Nothing happens when `emulator` is null.
+///
+/// # Safety
+///
+/// `emulator` must be null or come from `chip8_new`, and isn't valid anymore.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_free(emulator: *mut Chip8Emulator) {
+    if !emulator.is_null() {
+        drop(Box::from_raw(emulator));
+    }
+}
+
+/// The last error, valid until the next one or until the emulator is freed.
+///
+/// # Safety
+///
+/// `emulator` must be null or come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_last_error(emulator: *const Chip8Emulator) -> *const c_char {
+    emulator
+        .as_ref()
+        .map_or(NULL_EMULATOR.as_ptr() as *const c_char, |emulator| {
+            emulator.error.as_ptr()
+        })
+}
+
+/// Starts a program from scratch, with its settings from the ROM database
+/// or on the platform guessed from its instructions.
+///
+/// # Safety
+///
+/// `emulator` must be null or come from `chip8_new`, and `rom` must point to `size`
+/// readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_rom(
+    emulator: *mut Chip8Emulator,
+    rom: *const u8,
+    size: usize,
+) -> c_int {
+    let emulator = match emulator.as_mut() {
+        Some(emulator) => emulator,
+        None => return -1,
+    };
+    let result = bytes(rom, size)
+        .and_then(Chip8::for_rom)
+        .map(|(chip, known)| {
+            emulator.chip = chip;
+            emulator.palette = known.and_then(|info| info.palette).unwrap_or_default();
+        });
+    emulator.check(result)
+}
+
+/// # Safety
+///
+/// `emulator` must be null or come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_step_frame(emulator: *mut Chip8Emulator) -> c_int {
+    emulator.as_mut().map_or(-1, |emulator| {
+        emulator.chip.step_frame();
+        0
+    })
+}
+
+/// 64 or 128 pixels, it changes with the resolution of the program.
+///
+/// # Safety
+///
+/// `emulator` must be null or come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_width(emulator: *const Chip8Emulator) -> usize {
+    emulator
+        .as_ref()
+        .map_or(0, |emulator| emulator.chip.display().width())
+}
+
+/// 32 or 64 pixels.
+///
+/// # Safety
+///
+/// `emulator` must be null or come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_height(emulator: *const Chip8Emulator) -> usize {
+    emulator
+        .as_ref()
+        .map_or(0, |emulator| emulator.chip.display().height())
+}
+
+/// The display in RGBA, `chip8_width` × `chip8_height` pixels row by row,
+/// valid until the next call on the emulator.
+///
+/// # Safety
+///
+/// `emulator` must be null or come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_framebuffer(emulator: *mut Chip8Emulator) -> *const u8 {
+    let emulator = match emulator.as_mut() {
+        Some(emulator) => emulator,
+        None => return ptr::null(),
+    };
+    let display = emulator.chip.display();
+    emulator.frame.clear();
+    for y in 0..display.height() {
+        for x in 0..display.width() {
+            let [r, g, b] = emulator.palette.color(display.pixel(x, y));
+            emulator.frame.extend_from_slice(&[r, g, b, 0xFF]);
+        }
+    }
+    emulator.frame.as_ptr()
+}
+
+/// Presses or releases the keypad key 0 to F, the others are errors.
+///
+/// # Safety
+///
+/// `emulator` must be null or come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_key(
+    emulator: *mut Chip8Emulator,
+    key: u8,
+    is_pressed: bool,
+) -> c_int {
+    let emulator = match emulator.as_mut() {
+        Some(emulator) => emulator,
+        None => return -1,
+    };
+    let result = if key > 0xF {
+        Err(format!("no keypad key {}, they go from 0 to 15", key))
+    } else {
+        emulator.chip.set_key(key, is_pressed);
+        Ok(())
+    };
+    emulator.check(result)
+}
+
+/// # Safety
+///
+/// `emulator` must be null or come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_is_sound_playing(emulator: *const Chip8Emulator) -> bool {
+    emulator
+        .as_ref()
+        .is_some_and(|emulator| emulator.chip.is_sound_playing())
+}
+
+/// Copies the 16 bytes of the sound into `pattern`,
+/// played at 4000 * 2^((pitch - 64) / 48) bits per second.
+///
+/// # Safety
+///
+/// `emulator` must be null or come from `chip8_new`, and `pattern` must be null or
+/// point to 16 writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_audio_pattern(
+    emulator: *const Chip8Emulator,
+    pattern: *mut u8,
+) -> c_int {
+    let emulator = match emulator.as_ref() {
+        Some(emulator) => emulator,
+        None => return -1,
+    };
+    if pattern.is_null() {
+        return -1;
+    }
+    let source = emulator.chip.audio_pattern();
+    ptr::copy_nonoverlapping(source.as_ptr(), pattern, source.len());
+    0
+}
+
+/// # Safety
+///
+/// `emulator` must be null or come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_pitch(emulator: *const Chip8Emulator) -> u8 {
+    emulator
+        .as_ref()
+        .map_or(0, |emulator| emulator.chip.pitch())
+}
+
+/// A palette name or colours like `000000,FFFFFF`, as on the command line.
+///
+/// # Safety
+///
+/// `emulator` must be null or come from `chip8_new`, and `palette` must be null or
+/// a C string.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_palette(
+    emulator: *mut Chip8Emulator,
+    palette: *const c_char,
+) -> c_int {
+    let emulator = match emulator.as_mut() {
+        Some(emulator) => emulator,
+        None => return -1,
+    };
+    let result = if palette.is_null() {
+        Err("null palette".to_string())
+    } else {
+        CStr::from_ptr(palette)
+            .to_string_lossy()
+            .parse()
+            .map(|palette| emulator.palette = palette)
+    };
+    emulator.check(result)
+}
+
+/// Instructions executed per frame.
+///
+/// # Safety
+///
+/// `emulator` must be null or come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_speed(
+    emulator: *mut Chip8Emulator,
+    cycles_per_frame: u32,
+) -> c_int {
+    emulator.as_mut().map_or(-1, |emulator| {
+        emulator.chip.set_speed(cycles_per_frame);
+        0
+    })
+}
+
+/// Makes `CXNN` draw the same numbers at every run.
+///
+/// # Safety
+///
+/// `emulator` must be null or come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_seed(emulator: *mut Chip8Emulator, seed: u64) -> c_int {
+    emulator.as_mut().map_or(-1, |emulator| {
+        emulator.chip.set_seed(seed);
+        0
+    })
+}
+
+/// The memory of the machine, `chip8_memory_size` bytes.
+///
+/// # Safety
+///
+/// `emulator` must be null or come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_memory(emulator: *mut Chip8Emulator) -> *mut u8 {
+    emulator.as_mut().map_or(ptr::null_mut(), |emulator| {
+        emulator.chip.memory_mut().as_mut_ptr()
+    })
+}
+
+/// # Safety
+///
+/// `emulator` must be null or come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_memory_size(emulator: *const Chip8Emulator) -> usize {
+    emulator
+        .as_ref()
+        .map_or(0, |emulator| emulator.chip.memory().len())
+}
+
+/// A size large enough for the save states of the current platform.
+///
+/// # Safety
+///
+/// `emulator` must be null or come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_state_size(emulator: *const Chip8Emulator) -> usize {
+    emulator
+        .as_ref()
+        .map_or(0, |emulator| emulator.chip.state_size())
+}
+
+/// Writes the state of the machine into `state`, and gives its size,
+/// or 0 when `size` is too small.
+///
+/// # Safety
+///
+/// `emulator` must be null or come from `chip8_new`, and `state` must point to `size`
+/// writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_save_state(
+    emulator: *const Chip8Emulator,
+    state: *mut u8,
+    size: usize,
+) -> usize {
+    let emulator = match emulator.as_ref() {
+        Some(emulator) => emulator,
+        None => return 0,
+    };
+    let saved = emulator.chip.save_state();
+    if saved.len() > size || state.is_null() {
+        return 0;
+    }
+    ptr::copy_nonoverlapping(saved.as_ptr(), state, saved.len());
+    saved.len()
+}
+
+/// Puts the machine back in a state written by `chip8_save_state`,
+/// nothing changes when it is invalid.
+///
+/// # Safety
+///
+/// `emulator` must be null or come from `chip8_new`, and `state` must point to `size`
+/// readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_state(
+    emulator: *mut Chip8Emulator,
+    state: *const u8,
+    size: usize,
+) -> c_int {
+    let emulator = match emulator.as_mut() {
+        Some(emulator) => emulator,
+        None => return -1,
+    };
+    let result = bytes(state, size).and_then(|state| emulator.chip.load_state(state));
+    emulator.check(result)
+}
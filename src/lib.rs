@@ -0,0 +1,11 @@
+//! The emulator core, with the frontends enabled by the cargo features.
+
+pub mod chip8;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "libretro")]
+pub mod libretro;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "wasm")]
+pub mod wasm;
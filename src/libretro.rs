@@ -0,0 +1,560 @@
+//! A libretro core, to run the emulator inside RetroArch or any other libretro frontend.
+//!
+//! ```text
+//! cargo rustc --release --lib --crate-type cdylib --no-default-features --features libretro
+//! cp target/release/libchip_huit.so chip_huit_libretro.so
+//! ```
+//!
+//! The keyboard is mapped like in the emulator, the ROM database can change it.
+//! The joypad has the directions on 5 7 8 9, A on 6 and B on 4, like most modern games.
+//! The cheats are `address:value` pokes in hexadecimal, joined by `+`,
+//! written in the memory before each frame.
+
+#![allow(non_camel_case_types)]
+
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_uint, c_void};
+use std::ptr;
+use std::slice;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use crate::chip8::audio::{AudioRecorder, SAMPLE_RATE};
+use crate::chip8::display::{HIRES_HEIGHT, HIRES_WIDTH};
+use crate::chip8::graphic_engine::{Keymap, Palette};
+use crate::chip8::headless_interface::HeadlessInterface;
+use crate::chip8::{Chip8, FREQUENCY, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+const RETRO_API_VERSION: c_uint = 1;
+
+const RETRO_ENVIRONMENT_SET_PIXEL_FORMAT: c_uint = 10;
+const RETRO_ENVIRONMENT_SET_MEMORY_MAPS: c_uint = 36 | 0x10000;
+const RETRO_PIXEL_FORMAT_XRGB8888: c_uint = 1;
+
+const RETRO_DEVICE_JOYPAD: c_uint = 1;
+const RETRO_DEVICE_KEYBOARD: c_uint = 3;
+
+const RETRO_MEMORY_SYSTEM_RAM: c_uint = 2;
+const RETRO_MEMDESC_SYSTEM_RAM: u64 = 1 << 2;
+const RETRO_REGION_NTSC: c_uint = 0;
+
+/// The joypad buttons and their keypad keys.
+const JOYPAD: [(c_uint, u8); 6] = [
+    (4, 0x5), // up
+    (6, 0x7), // left
+    (5, 0x8), // down
+    (7, 0x9), // right
+    (8, 0x6), // A
+    (0, 0x4), // B
+];
+
+#[repr(C)]
+pub struct retro_system_info {
+    pub library_name: *const c_char,
+    pub library_version: *const c_char,
+    pub valid_extensions: *const c_char,
+    pub need_fullpath: bool,
+    pub block_extract: bool,
+}
+
+#[repr(C)]
+pub struct retro_game_geometry {
+    pub base_width: c_uint,
+    pub base_height: c_uint,
+    pub max_width: c_uint,
+    pub max_height: c_uint,
+    pub aspect_ratio: f32,
+}
+
+#[repr(C)]
+pub struct retro_system_timing {
+    pub fps: f64,
+    pub sample_rate: f64,
+}
+
+#[repr(C)]
+pub struct retro_system_av_info {
+    pub geometry: retro_game_geometry,
+    pub timing: retro_system_timing,
+}
+
+#[repr(C)]
+pub struct retro_game_info {
+    pub path: *const c_char,
+    pub data: *const c_void,
+    pub size: usize,
+    pub meta: *const c_char,
+}
+
+#[repr(C)]
+pub struct retro_memory_descriptor {
+    pub flags: u64,
+    pub ptr: *mut c_void,
+    pub offset: usize,
+    pub start: usize,
+    pub select: usize,
+    pub disconnect: usize,
+    pub len: usize,
+    pub addrspace: *const c_char,
+}
+
+#[repr(C)]
+pub struct retro_memory_map {
+    pub descriptors: *const retro_memory_descriptor,
+    pub num_descriptors: c_uint,
+}
+
+pub type retro_environment_t = unsafe extern "C" fn(cmd: c_uint, data: *mut c_void) -> bool;
+pub type retro_video_refresh_t =
+    unsafe extern "C" fn(data: *const c_void, width: c_uint, height: c_uint, pitch: usize);
+pub type retro_audio_sample_t = unsafe extern "C" fn(left: i16, right: i16);
+pub type retro_audio_sample_batch_t =
+    unsafe extern "C" fn(data: *const i16, frames: usize) -> usize;
+pub type retro_input_poll_t = unsafe extern "C" fn();
+pub type retro_input_state_t =
+    unsafe extern "C" fn(port: c_uint, device: c_uint, index: c_uint, id: c_uint) -> i16;
+
+/// The frontend callbacks and the game running.
+struct Core {
+    environment: Option<retro_environment_t>,
+    video_refresh: Option<retro_video_refresh_t>,
+    audio_sample_batch: Option<retro_audio_sample_batch_t>,
+    input_poll: Option<retro_input_poll_t>,
+    input_state: Option<retro_input_state_t>,
+    game: Option<Game>,
+}
+
+struct Game {
+    chip: Chip8<HeadlessInterface>,
+    rom: Vec<u8>,
+    palette: Palette,
+    keymap: Keymap,
+    /// The display in `0RGB`, row by row.
+    frame: Vec<u32>,
+    /// The pokes of each enabled cheat.
+    cheats: Vec<(c_uint, Vec<(usize, u8)>)>,
+}
+
+// the frontends call the core from a single thread, the lock is never held while
+// calling them back so that they can call the core again
+static CORE: Mutex<Core> = Mutex::new(Core {
+    environment: None,
+    video_refresh: None,
+    audio_sample_batch: None,
+    input_poll: None,
+    input_state: None,
+    game: None,
+});
+
+fn core() -> MutexGuard<'static, Core> {
+    CORE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn with_core<T>(f: impl FnOnce(&mut Core) -> T) -> T {
+    f(&mut core())
+}
+
+fn with_game<T>(default: T, f: impl FnOnce(&mut Game) -> T) -> T {
+    with_core(|core| core.game.as_mut().map_or(default, f))
+}
+
+/// Runs a frame, calling the frontend between the steps that need the core.
+fn run() {
+    let (input_poll, input_state, keymap) = match *core() {
+        Core {
+            game: Some(ref game),
+            input_poll,
+            input_state,
+            ..
+        } => (input_poll, input_state, game.keymap),
+        _ => return,
+    };
+
+    if let Some(input_poll) = input_poll {
+        unsafe { input_poll() };
+    }
+    let keypad = input_state.map(|input_state| {
+        let mut keypad = [false; 16];
+        for c in ('0'..='9').chain('a'..='z') {
+            if let Some(key) = keymap.key(c) {
+                // the libretro keycodes of the digits and letters are their ASCII codes
+                keypad[key as usize] |=
+                    unsafe { input_state(0, RETRO_DEVICE_KEYBOARD, 0, c as c_uint) } != 0;
+            }
+        }
+        for &(button, key) in JOYPAD.iter() {
+            keypad[key as usize] |= unsafe { input_state(0, RETRO_DEVICE_JOYPAD, 0, button) } != 0;
+        }
+        keypad
+    });
+
+    // the frame is given back to the game once shown, to keep its buffer
+    let (video_refresh, audio_sample_batch, (frame, width, height, samples)) = {
+        let mut core = core();
+        let (video_refresh, audio_sample_batch) = (core.video_refresh, core.audio_sample_batch);
+        let game = match core.game {
+            Some(ref mut game) => game,
+            None => return,
+        };
+        (video_refresh, audio_sample_batch, game.step(keypad))
+    };
+
+    if let Some(video_refresh) = video_refresh {
+        unsafe {
+            video_refresh(
+                frame.as_ptr() as *const c_void,
+                width as c_uint,
+                height as c_uint,
+                width * 4,
+            )
+        };
+    }
+    if let Some(audio_sample_batch) = audio_sample_batch {
+        let stereo: Vec<i16> = samples
+            .iter()
+            .flat_map(|&sample| [sample, sample])
+            .collect();
+        unsafe { audio_sample_batch(stereo.as_ptr(), samples.len()) };
+    }
+
+    with_game((), |game| game.frame = frame);
+}
+
+impl Game {
+    /// Runs a frame with the keys pressed, and gives the display in `0RGB` with its
+    /// size and the sound.
+    fn step(&mut self, keypad: Option<[bool; 16]>) -> (Vec<u32>, usize, usize, Vec<i16>) {
+        if let Some(keypad) = keypad {
+            for (key, &is_pressed) in keypad.iter().enumerate() {
+                self.chip.set_key(key as u8, is_pressed);
+            }
+        }
+
+        for (_, pokes) in self.cheats.iter() {
+            let memory = self.chip.memory_mut();
+            for &(address, value) in pokes {
+                if let Some(byte) = memory.get_mut(address) {
+                    *byte = value;
+                }
+            }
+        }
+
+        self.chip.step_frame();
+
+        let display = self.chip.display();
+        let (width, height) = (display.width(), display.height());
+        let mut frame = std::mem::take(&mut self.frame);
+        frame.clear();
+        for y in 0..height {
+            for x in 0..width {
+                let [r, g, b] = self.palette.color(display.pixel(x, y));
+                frame.push(u32::from_be_bytes([0, r, g, b]));
+            }
+        }
+
+        let samples = self
+            .chip
+            .audio_recorder_mut()
+            .map(AudioRecorder::take_samples)
+            .unwrap_or_default();
+        (frame, width, height, samples)
+    }
+}
+
+/// Tells the frontend where the memory is, for the cheat search and the achievements.
+fn set_memory_maps() {
+    let (environment, memory, len) = match *core() {
+        Core {
+            environment: Some(environment),
+            game: Some(ref mut game),
+            ..
+        } => {
+            let memory = game.chip.memory_mut();
+            (environment, memory.as_mut_ptr(), memory.len())
+        }
+        _ => return,
+    };
+    // the memory stays at the same address while the game is loaded
+    let descriptor = retro_memory_descriptor {
+        flags: RETRO_MEMDESC_SYSTEM_RAM,
+        ptr: memory as *mut c_void,
+        offset: 0,
+        start: 0,
+        select: 0,
+        disconnect: 0,
+        len,
+        addrspace: ptr::null(),
+    };
+    let mut map = retro_memory_map {
+        descriptors: &descriptor,
+        num_descriptors: 1,
+    };
+    unsafe {
+        environment(
+            RETRO_ENVIRONMENT_SET_MEMORY_MAPS,
+            &mut map as *mut retro_memory_map as *mut c_void,
+        )
+    };
+}
+
+/// Parses `address:value` pokes joined by `+`, in hexadecimal.
+fn parse_cheat(code: &str) -> Result<Vec<(usize, u8)>, String> {
+    code.split('+')
+        .map(|poke| {
+            let (address, value) = poke
+                .trim()
+                .split_once(':')
+                .ok_or_else(|| format!("the cheat \"{}\" isn't address:value", poke))?;
+            let address = usize::from_str_radix(address.trim(), 16)
+                .map_err(|error| format!("invalid cheat address \"{}\": {}", address, error))?;
+            let value = u8::from_str_radix(value.trim(), 16)
+                .map_err(|error| format!("invalid cheat value \"{}\": {}", value, error))?;
+            Ok((address, value))
+        })
+        .collect()
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    RETRO_API_VERSION
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    with_core(|core| core.game = None);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_environment(environment: retro_environment_t) {
+    with_core(|core| core.environment = Some(environment));
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(video_refresh: retro_video_refresh_t) {
+    with_core(|core| core.video_refresh = Some(video_refresh));
+}
+
+/// Unused, the sound is sent in batches.
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_audio_sample: retro_audio_sample_t) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(audio_sample_batch: retro_audio_sample_batch_t) {
+    with_core(|core| core.audio_sample_batch = Some(audio_sample_batch));
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(input_poll: retro_input_poll_t) {
+    with_core(|core| core.input_poll = Some(input_poll));
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(input_state: retro_input_state_t) {
+    with_core(|core| core.input_state = Some(input_state));
+}
+
+/// # Safety
+///
+/// `info` must point to a `retro_system_info`.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut retro_system_info) {
+    *info = retro_system_info {
+        library_name: b"chip_huit\0".as_ptr() as *const c_char,
+        library_version: concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char,
+        valid_extensions: b"ch8|c8|sc8|xo8\0".as_ptr() as *const c_char,
+        need_fullpath: false,
+        block_extract: false,
+    };
+}
+
+/// # Safety
+///
+/// `info` must point to a `retro_system_av_info`.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut retro_system_av_info) {
+    *info = retro_system_av_info {
+        geometry: retro_game_geometry {
+            base_width: SCREEN_WIDTH,
+            base_height: SCREEN_HEIGHT,
+            max_width: HIRES_WIDTH,
+            max_height: HIRES_HEIGHT,
+            aspect_ratio: 2.,
+        },
+        timing: retro_system_timing {
+            fps: FREQUENCY as f64,
+            sample_rate: SAMPLE_RATE as f64,
+        },
+    };
+}
+
+/// There is a single keypad, whatever the device plugged.
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+/// Starts the program again, with a new seed. The memory stays at the same address.
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    with_game((), |game| match Chip8::for_rom(&game.rom) {
+        Ok((chip, _)) => {
+            if let Err(error) = game.chip.load_state(&chip.save_state()) {
+                eprintln!("error: {}", error);
+            }
+        }
+        Err(error) => eprintln!("error: {}", error),
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn retro_run() {
+    run();
+}
+
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    with_game(0, |game| game.chip.state_size())
+}
+
+/// # Safety
+///
+/// `data` must point to `size` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    with_game(false, |game| {
+        let state = game.chip.save_state();
+        if state.len() > size {
+            return false;
+        }
+        let data = slice::from_raw_parts_mut(data as *mut u8, size);
+        data[..state.len()].copy_from_slice(&state);
+        data[state.len()..].fill(0);
+        true
+    })
+}
+
+/// # Safety
+///
+/// `data` must point to `size` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    with_game(false, |game| {
+        let state = slice::from_raw_parts(data as *const u8, size);
+        match game.chip.load_state(state) {
+            Ok(()) => true,
+            Err(error) => {
+                eprintln!("error: {}", error);
+                false
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {
+    with_game((), |game| game.cheats.clear());
+}
+
+/// # Safety
+///
+/// `code` must be a C string.
+#[no_mangle]
+pub unsafe extern "C" fn retro_cheat_set(index: c_uint, enabled: bool, code: *const c_char) {
+    let code = CStr::from_ptr(code).to_string_lossy();
+    with_game((), |game| {
+        game.cheats.retain(|(cheat, _)| *cheat != index);
+        if enabled {
+            match parse_cheat(&code) {
+                Ok(pokes) => game.cheats.push((index, pokes)),
+                Err(error) => eprintln!("error: {}", error),
+            }
+        }
+    });
+}
+
+/// # Safety
+///
+/// `info` must point to a `retro_game_info` with the ROM in `data`.
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(info: *const retro_game_info) -> bool {
+    // the program has to be loaded with the game, there is nothing to run without
+    if info.is_null() || (*info).data.is_null() {
+        return false;
+    }
+    let rom = slice::from_raw_parts((*info).data as *const u8, (*info).size).to_vec();
+
+    let environment = match core().environment {
+        Some(environment) => environment,
+        None => return false,
+    };
+    let mut format = RETRO_PIXEL_FORMAT_XRGB8888;
+    if !environment(
+        RETRO_ENVIRONMENT_SET_PIXEL_FORMAT,
+        &mut format as *mut c_uint as *mut c_void,
+    ) {
+        eprintln!("error: the frontend doesn't support the XRGB8888 pixel format");
+        return false;
+    }
+
+    let (mut chip, known) = match Chip8::for_rom(&rom) {
+        Ok(chip) => chip,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            return false;
+        }
+    };
+    chip.set_audio_recorder(AudioRecorder::in_memory());
+    chip.start();
+
+    core().game = Some(Game {
+        chip,
+        rom,
+        palette: known
+            .as_ref()
+            .and_then(|info| info.palette)
+            .unwrap_or_default(),
+        keymap: known.and_then(|info| info.keymap).unwrap_or_default(),
+        frame: Vec::with_capacity((HIRES_WIDTH * HIRES_HEIGHT) as usize),
+        cheats: Vec::new(),
+    });
+    set_memory_maps();
+    true
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(
+    _game_type: c_uint,
+    _info: *const retro_game_info,
+    _num_info: usize,
+) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    with_core(|core| core.game = None);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    RETRO_REGION_NTSC
+}
+
+/// Only the system memory, the SUPER-CHIP flags aren't saved.
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(id: c_uint) -> *mut c_void {
+    if id != RETRO_MEMORY_SYSTEM_RAM {
+        return ptr::null_mut();
+    }
+    with_game(ptr::null_mut(), |game| {
+        game.chip.memory_mut().as_mut_ptr() as *mut c_void
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(id: c_uint) -> usize {
+    if id != RETRO_MEMORY_SYSTEM_RAM {
+        return 0;
+    }
+    with_game(0, |game| game.chip.memory().len())
+}
@@ -1,12 +1,614 @@
-mod chip8;
+mod config;
 
-use chip8::Chip8;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use clap::{Args, Parser, Subcommand};
+
+use chip_huit::chip8::assembler;
+use chip_huit::chip8::audio::AudioRecorder;
+use chip_huit::chip8::batch::{self, BatchFile};
+use chip_huit::chip8::database::{self, RomInfo};
+use chip_huit::chip8::disassembler::{Disassembly, Syntax};
+use chip_huit::chip8::frontends::{self, Frontend, HEADLESS};
+use chip_huit::chip8::gif_recorder::GifRecorder;
+use chip_huit::chip8::graphic_engine::{EngineSettings, GraphicEngine, Palette};
+use chip_huit::chip8::headless_interface::HeadlessInterface;
+use chip_huit::chip8::screenshot;
+use chip_huit::chip8::trace_diff::{self, Replay};
+use chip_huit::chip8::tracer::Tracer;
+use chip_huit::chip8::video_recorder::{VideoFormat, VideoRecorder};
+use chip_huit::chip8::{Chip8, FREQUENCY};
+use config::Config;
+
+/// A CHIP-8, SuperChip and XO-CHIP emulator with its development tools.
+#[derive(Parser)]
+#[command(
+    name = "chip_huit",
+    version,
+    args_conflicts_with_subcommands = true,
+    arg_required_else_help = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// The ROM to run
+    rom: Option<PathBuf>,
+    #[command(flatten)]
+    options: EmulationOptions,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Runs a ROM (the default command)
+    Run(RunOptions),
+    /// Prints the disassembly of a ROM
+    Disasm {
+        rom: PathBuf,
+        /// Writes Octo source instead of classic mnemonics
+        #[arg(long)]
+        octo: bool,
+    },
+    /// Describes a ROM: size, platform, code and data
+    Info { rom: PathBuf },
+    /// Assembles Octo source into a ROM and a `.sym` symbol map
+    Asm {
+        source: PathBuf,
+        /// The ROM to write, the source with the `.ch8` extension by default
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Runs the ROM once assembled
+        #[arg(long)]
+        run: bool,
+        #[command(flatten)]
+        options: EmulationOptions,
+    },
+    /// Finds where two execution traces diverge, exits with 2 if they do
+    Tracediff {
+        ours: PathBuf,
+        reference: PathBuf,
+        /// Number of instructions shown before the divergence
+        #[arg(long, default_value_t = 10)]
+        context: usize,
+        /// The traced ROM, replayed up to the divergence to show the memory around PC and I,
+        /// with the options and the --seed of our trace
+        #[arg(long)]
+        rom: Option<PathBuf>,
+        #[command(flatten)]
+        options: EmulationOptions,
+    },
+    /// Runs the jobs of a TOML file on headless emulators in parallel,
+    /// and prints a JSON line per run
+    Batch {
+        jobs: PathBuf,
+        /// [default: the number of cores]
+        #[arg(long)]
+        threads: Option<usize>,
+        /// Writes the last frame of each run into this directory
+        #[arg(long, value_name = "DIR")]
+        screenshots: Option<PathBuf>,
+        /// Writes the memory of each run into this directory
+        #[arg(long, value_name = "DIR")]
+        memory: Option<PathBuf>,
+    },
+}
+
+#[derive(Args)]
+struct RunOptions {
+    /// The ROM to run
+    rom: PathBuf,
+    #[command(flatten)]
+    options: EmulationOptions,
+}
+
+#[derive(Args)]
+struct EmulationOptions {
+    #[command(flatten)]
+    settings: config::Settings,
+    /// The config file, with the default settings and the settings of each ROM
+    /// [default: ~/.config/chip_huit/config.toml]
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Seed of the random numbers, for reproducible runs
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Stops the headless backend after this number of frames
+    #[arg(long)]
+    frames: Option<u64>,
+    /// Runs headless and writes a screenshot after this number of frames
+    #[arg(long, value_name = "FRAMES")]
+    screenshot_after: Option<u64>,
+    /// The screenshot of --screenshot-after, a 1-bit PBM if it ends with `.pbm`
+    /// [default: the ROM name with the `.png` extension]
+    #[arg(long)]
+    screenshot: Option<PathBuf>,
+    /// Records the sound into this WAV file, in emulated time whatever the backend
+    #[arg(long, value_name = "WAV")]
+    audio: Option<PathBuf>,
+    /// Runs headless and records every frame into this GIF, until the program
+    /// stops or for --frames frames
+    #[arg(long, value_name = "GIF")]
+    record: Option<PathBuf>,
+    /// Runs headless and writes every frame into this video, `-` for stdout,
+    /// until the program stops or for --frames frames
+    #[arg(long)]
+    video: Option<PathBuf>,
+    /// y4m, or rgb24 for frames after a `RGB24 W<width> H<height> F<fps>` line
+    #[arg(long, default_value = "y4m")]
+    video_format: VideoFormat,
+    /// Trace filter like `debug,draw,0x200-0x2FF` [default: $CHIP8_TRACE]
+    #[arg(long)]
+    trace: Option<String>,
+    /// Writes the instruction trace to this file [default: $CHIP8_TRACE_FILE]
+    #[arg(long)]
+    trace_file: Option<PathBuf>,
+}
 
 fn main() {
-    let mut chip = Chip8::new();
-    chip.read(std::path::Path::new(
-        "/home/ityt/Téléchargements/Maze [David Winter, 199x].ch8",
-    ));
+    let Cli {
+        command,
+        rom,
+        options,
+    } = Cli::parse();
+
+    let result = match (command, rom) {
+        (Some(Command::Run(run)), _) => {
+            read_rom(&run.rom).and_then(|rom| run_rom(&rom, &file_name(&run.rom), run.options))
+        }
+        (None, Some(path)) => {
+            read_rom(&path).and_then(|rom| run_rom(&rom, &file_name(&path), options))
+        }
+        (Some(Command::Disasm { rom, octo }), _) => disasm(&rom, octo),
+        (Some(Command::Info { rom }), _) => info(&rom),
+        (
+            Some(Command::Asm {
+                source,
+                output,
+                run,
+                options,
+            }),
+            _,
+        ) => asm(&source, output, run, options),
+        (
+            Some(Command::Tracediff {
+                ours,
+                reference,
+                context,
+                rom,
+                options,
+            }),
+            _,
+        ) => tracediff(&ours, &reference, context, rom, options),
+        (
+            Some(Command::Batch {
+                jobs,
+                threads,
+                screenshots,
+                memory,
+            }),
+            _,
+        ) => run_batch(&jobs, threads, screenshots, memory),
+        (None, None) => {
+            eprintln!("Usage: chip_huit [OPTIONS] <ROM>, see chip_huit --help");
+            process::exit(1);
+        }
+    };
+
+    if let Err(error) = result {
+        eprintln!("error: {}", error);
+        process::exit(1);
+    }
+}
+
+fn read_rom(path: &Path) -> Result<Vec<u8>, String> {
+    let rom = std::fs::read(path).map_err(|error| match error.kind() {
+        std::io::ErrorKind::NotFound => format!("the ROM '{}' doesn't exist", path.display()),
+        _ => format!("cannot read '{}': {}", path.display(), error),
+    })?;
+
+    if rom.is_empty() {
+        return Err(format!("'{}' is empty", path.display()));
+    }
+
+    Ok(rom)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// The command line first, then the config file and the ROM database.
+fn settings(
+    rom: &[u8],
+    known: Option<&RomInfo>,
+    options: EmulationOptions,
+) -> Result<config::Settings, String> {
+    let config = match options.config {
+        Some(ref path) => Config::load(path, true)?,
+        None => match Config::default_path() {
+            Some(ref path) => Config::load(path, false)?,
+            None => Config::default(),
+        },
+    };
+
+    let known = known.map(config::Settings::from).unwrap_or_default();
+    Ok(options.settings.or(config.settings(rom, known)))
+}
+
+/// `name` is the file name, shown when the ROM isn't in the database.
+fn run_rom(rom: &[u8], name: &str, options: EmulationOptions) -> Result<(), String> {
+    let tracer = tracer(&options)?;
+    let (seed, frames, record) = (options.seed, options.frames, options.record.clone());
+    let audio = options.audio.clone();
+    let (video, video_format) = (options.video.clone(), options.video_format);
+    // the messages can't mix with the video
+    let report = |message: String| match video {
+        Some(ref path) if path == Path::new("-") => eprintln!("{}", message),
+        _ => println!("{}", message),
+    };
+    let screenshot = options.screenshot_after.map(|frame| {
+        let path = options
+            .screenshot
+            .clone()
+            .unwrap_or_else(|| Path::new(name).with_extension("png"));
+        (frame, path)
+    });
+    let known = database::lookup(rom);
+    let settings = settings(rom, known.as_ref(), options)?;
+
+    let title = match known {
+        Some(ref info) => {
+            report(format!("Identified '{}'.", info.description()));
+            info.title.clone()
+        }
+        None => {
+            report(format!(
+                "'{}' isn't in the ROM database (SHA-1: {}).",
+                name,
+                database::rom_hash(rom)
+            ));
+            name.to_string()
+        }
+    };
+
+    let engine_settings = EngineSettings {
+        title: format!("{} - chip_huit", title),
+        name: Path::new(name)
+            .file_stem()
+            .map_or(name.to_string(), |stem| stem.to_string_lossy().into_owned()),
+        scale: settings.scale.unwrap_or(EngineSettings::default().scale),
+        scaling: settings
+            .scaling
+            .unwrap_or(EngineSettings::default().scaling),
+        palette: settings.palette.unwrap_or_default(),
+        keymap: settings.keymap.unwrap_or_default(),
+        persistence: settings.persistence,
+        renderer: settings
+            .renderer
+            .unwrap_or(EngineSettings::default().renderer),
+    };
+
+    if engine_settings.scale == 0 {
+        return Err("the scale must be at least 1".to_string());
+    }
+
+    let frontend: Option<Frontend> = if screenshot.is_some() || record.is_some() || video.is_some()
+    {
+        Some(HEADLESS.parse()?)
+    } else {
+        settings.backend
+    };
+    let g_engine: Box<dyn GraphicEngine> = match frontend {
+        Some(frontend) if frontend.is_headless() => {
+            // without --frames, the screenshot is the last frame
+            let frames = frames.or_else(|| screenshot.as_ref().map(|(frame, _)| *frame));
+            let recorder = match record {
+                Some(ref path) => Some(GifRecorder::create(
+                    path,
+                    &engine_settings.palette,
+                    engine_settings.scale,
+                )?),
+                None => None,
+            };
+            let video_recorder = match video {
+                Some(ref path) => Some(VideoRecorder::create(
+                    path,
+                    video_format,
+                    &engine_settings.palette,
+                    engine_settings.scale,
+                )?),
+                None => None,
+            };
+            let mut headless = HeadlessInterface::new(frames, engine_settings);
+            if let Some((frame, path)) = screenshot {
+                headless.set_screenshot(frame, path);
+            }
+            if let Some(recorder) = recorder {
+                headless.set_recording(recorder);
+            }
+            if let Some(video_recorder) = video_recorder {
+                headless.set_video(video_recorder);
+            }
+            Box::new(headless)
+        }
+        Some(frontend) => (frontend.create)(engine_settings)?,
+        None => frontends::first_available(engine_settings)?,
+    };
+
+    let mut chip = Chip8::new(g_engine);
+    set_up(&mut chip, rom, &settings);
+    if let Some(seed) = seed {
+        chip.set_seed(seed);
+    }
+    if let Some(ref path) = audio {
+        chip.set_audio_recorder(AudioRecorder::create(path)?);
+    }
+    chip.set_tracer(tracer);
+    chip.load(rom)?;
+
+    run(&mut chip);
+    chip.finish()?;
+
+    if let Some(error) = chip.audio_error() {
+        return Err(error.to_string());
+    }
+    if let Some(recorder) = chip.take_audio_recorder() {
+        let path = recorder.path().to_path_buf();
+        recorder.finish()?;
+        report(format!("Saved the audio '{}'.", path.display()));
+    }
+    Ok(())
+}
+
+/// Selects the platform, the quirks and the speed of `settings`, before loading `rom`.
+fn set_up<E: GraphicEngine + ?Sized>(chip: &mut Chip8<E>, rom: &[u8], settings: &config::Settings) {
+    chip.set_platform(
+        settings
+            .platform
+            .unwrap_or_else(|| Disassembly::new(rom).platform()),
+    );
+    if let Some(quirks) = settings.quirks {
+        chip.set_quirks(quirks);
+    }
+    if let Some(speed) = settings.speed {
+        chip.set_speed(speed);
+    }
+}
+
+/// Runs at `FREQUENCY` frames per second, or as fast as possible when headless.
+fn run(chip: &mut Chip8) {
+    chip.start();
+
+    let frame_duration = Duration::from_secs(1) / FREQUENCY;
+
+    let mut has_halted = false;
+
+    while chip.is_running() {
+        let frame_start = Instant::now();
+
+        chip.step_frame();
+
+        // once, the window stays open after
+        if let (false, Some(reason)) = (has_halted, chip.halt_reason()) {
+            eprintln!("{}, stopping execution!", reason);
+            has_halted = true;
+        }
+
+        if !chip.is_headless() {
+            if let Some(rest) = frame_duration.checked_sub(frame_start.elapsed()) {
+                thread::sleep(rest);
+            }
+        }
+    }
+}
+
+/// The options first, then `CHIP8_TRACE` and `CHIP8_TRACE_FILE`.
+fn tracer(options: &EmulationOptions) -> Result<Tracer, String> {
+    let mut tracer = match options.trace {
+        Some(ref filter) => Tracer::parse(filter)?,
+        None => Tracer::from_env()?,
+    };
+
+    if let Some(ref path) = options.trace_file {
+        tracer.set_file(path)?;
+    }
+
+    Ok(tracer)
+}
+
+fn disasm(path: &Path, octo: bool) -> Result<(), String> {
+    let syntax = if octo { Syntax::Octo } else { Syntax::Classic };
+    let rom = read_rom(path)?;
+
+    print!("{}", Disassembly::new(&rom).to_source(syntax));
+    Ok(())
+}
+
+fn info(path: &Path) -> Result<(), String> {
+    let rom = read_rom(path)?;
+    let disassembly = Disassembly::new(&rom);
+    let code_size = disassembly.code_size();
+
+    println!("File:     {}", path.display());
+    println!("Size:     {} bytes", rom.len());
+    if let Some(info) = database::lookup(&rom) {
+        println!("Title:    {}", info.description());
+    }
+    println!("SHA-1:    {}", database::rom_hash(&rom));
+    println!("Platform: {}", disassembly.platform());
+    println!("Code:     {} bytes", code_size);
+    println!("Data:     {} bytes", rom.len() - code_size);
+    Ok(())
+}
+
+/// Writes the ROM and a `.sym` symbol map next to it.
+fn asm(
+    source_path: &Path,
+    output: Option<PathBuf>,
+    run: bool,
+    options: EmulationOptions,
+) -> Result<(), String> {
+    let source = std::fs::read_to_string(source_path)
+        .map_err(|error| format!("cannot read '{}': {}", source_path.display(), error))?;
+
+    let program = assembler::assemble(&source)
+        .map_err(|error| format!("{}: {}", source_path.display(), error))?;
+
+    let rom_path = output.unwrap_or_else(|| source_path.with_extension("ch8"));
+
+    std::fs::write(&rom_path, &program.rom)
+        .and_then(|_| std::fs::write(rom_path.with_extension("sym"), program.symbol_map()))
+        .map_err(|error| format!("cannot write '{}': {}", rom_path.display(), error))?;
+
+    println!(
+        "Assembled {} bytes into '{}'.",
+        program.rom.len(),
+        rom_path.display()
+    );
+
+    if run {
+        run_rom(&program.rom, &file_name(&rom_path), options)?;
+    }
+    Ok(())
+}
+
+fn tracediff(
+    ours: &Path,
+    reference: &Path,
+    context: usize,
+    rom: Option<PathBuf>,
+    options: EmulationOptions,
+) -> Result<(), String> {
+    let mut traces = Vec::new();
+    for path in [ours, reference].iter() {
+        let trace = std::fs::read_to_string(path)
+            .map_err(|error| error.to_string())
+            .and_then(|text| trace_diff::parse(&text))
+            .map_err(|error| format!("cannot read '{}': {}", path.display(), error))?;
+        traces.push(trace);
+    }
+
+    let chip = match (rom, trace_diff::first_divergence(&traces[0], &traces[1])) {
+        (Some(path), Some(index)) => {
+            // the random numbers of the replay must be the ones of our trace
+            let seed = options
+                .seed
+                .ok_or("the replay needs the --seed of our trace")?;
+            let rom = read_rom(&path)?;
+            let known = database::lookup(&rom);
+            let settings = settings(&rom, known.as_ref(), options)?;
+
+            let mut chip = Chip8::headless();
+            set_up(&mut chip, &rom, &settings);
+            chip.set_seed(seed);
+            chip.load(&rom)?;
+            chip.start();
+            chip.run_to_cycle(traces[0][index].cycle.unwrap_or(index as u64));
+            Some((chip, &traces[0][index]))
+        }
+        _ => None,
+    };
+    let replay = chip.as_ref().map(|(chip, line)| {
+        if (chip.pc(), chip.registers(), chip.index()) == (line.pc, &line.v, line.i) {
+            Replay::Memory(chip.memory())
+        } else {
+            let v: Vec<String> = chip
+                .registers()
+                .iter()
+                .map(|v| format!("{:02X}", v))
+                .collect();
+            Replay::Diverged(format!(
+                "PC {:04X}, I {:04X} and V {}",
+                chip.pc(),
+                chip.index(),
+                v.join(" ")
+            ))
+        }
+    });
+
+    match trace_diff::report(&traces[0], &traces[1], context, replay) {
+        Some(report) => {
+            print!("{}", report);
+            process::exit(2);
+        }
+        None => println!(
+            "The traces are identical ({} instructions).",
+            traces[0].len()
+        ),
+    }
+    Ok(())
+}
+
+fn run_batch(
+    path: &Path,
+    threads: Option<usize>,
+    screenshots: Option<PathBuf>,
+    memory: Option<PathBuf>,
+) -> Result<(), String> {
+    let directory = path.parent().unwrap_or(Path::new(""));
+    let jobs = BatchFile::load(path)?.jobs(directory)?;
+    for directory in screenshots.iter().chain(memory.iter()) {
+        std::fs::create_dir_all(directory)
+            .map_err(|error| format!("cannot create '{}': {}", directory.display(), error))?;
+    }
+
+    let threads = threads.unwrap_or_else(batch::default_threads);
+    let start = Instant::now();
+    let results = batch::run_all(&jobs, threads, |index, chip, frames| {
+        let job = &jobs[index];
+        let stem = Path::new(&job.name)
+            .file_stem()
+            .map_or(job.name.clone(), |stem| stem.to_string_lossy().into_owned());
+        let name = format!("{}-{}-{}", index, stem, job.seed);
+
+        if let Some(ref directory) = screenshots {
+            let path = directory.join(&name).with_extension("png");
+            screenshot::save(
+                chip.display(),
+                &Palette::default(),
+                EngineSettings::default().scale,
+                &path,
+            )?;
+        }
+        if let Some(ref directory) = memory {
+            let path = directory.join(&name).with_extension("bin");
+            std::fs::write(&path, chip.memory())
+                .map_err(|error| format!("cannot write '{}': {}", path.display(), error))?;
+        }
+
+        Ok(serde_json::json!({
+            "rom": job.name,
+            "seed": job.seed,
+            "frames": frames,
+            "state": batch::hash(&chip.save_state()),
+            "display": batch::hash(chip.display().pixels()),
+        }))
+    });
+
+    let mut failures = 0;
+    for (job, result) in jobs.iter().zip(results) {
+        match result.and_then(|report| report) {
+            Ok(report) => println!("{}", report),
+            Err(error) => {
+                eprintln!("error: {} with the seed {}: {}", job.name, job.seed, error);
+                failures += 1;
+            }
+        }
+    }
+    eprintln!(
+        "Ran {} jobs on {} threads in {:.2} s.",
+        jobs.len(),
+        threads,
+        start.elapsed().as_secs_f64()
+    );
 
-    chip.run();
+    match failures {
+        0 => Ok(()),
+        _ => Err(format!("{} of the {} jobs failed", failures, jobs.len())),
+    }
 }
@@ -0,0 +1,332 @@
+//! The Python module, built into a wheel by maturin with `pyproject.toml`.
+//!
+//! ```text
+//! maturin develop --release
+//! ```
+//!
+//! ```python
+//! import chip_huit
+//!
+//! chip = chip_huit.Chip8()
+//! chip.load_rom(open("pong.ch8", "rb").read())
+//! chip.step(60)
+//! pixels = chip.framebuffer()  # numpy.uint8, height × width
+//! ```
+
+use numpy::ndarray::{Array2, Array3};
+use numpy::{IntoPyArray, PyArray2, PyArray3};
+use pyo3::exceptions::{PyIndexError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use crate::chip8::display::Display;
+use crate::chip8::environment::{self, GameSpec};
+use crate::chip8::graphic_engine::Palette;
+use crate::chip8::headless_interface::HeadlessInterface;
+use crate::chip8::Chip8;
+
+/// A headless CHIP-8, SUPER-CHIP or XO-CHIP, stepped by the script.
+#[pyclass(name = "Chip8", unsendable)]
+pub struct Emulator {
+    chip: Chip8<HeadlessInterface>,
+    palette: Palette,
+}
+
+fn value_error(error: String) -> PyErr {
+    PyValueError::new_err(error)
+}
+
+/// The planes of each pixel, height × width.
+fn planes<'py>(py: Python<'py>, display: &Display) -> Bound<'py, PyArray2<u8>> {
+    Array2::from_shape_fn((display.height(), display.width()), |(y, x)| {
+        display.pixel(x, y)
+    })
+    .into_pyarray(py)
+}
+
+impl Emulator {
+    fn check_key(key: u8) -> PyResult<u8> {
+        if key > 0xF {
+            return Err(PyValueError::new_err(format!(
+                "no keypad key {}, they go from 0 to 15",
+                key
+            )));
+        }
+        Ok(key)
+    }
+
+    /// The bounds of `length` bytes at `address`.
+    fn memory_range(&self, address: usize, length: usize) -> PyResult<std::ops::Range<usize>> {
+        let size = self.chip.memory().len();
+        match address.checked_add(length) {
+            Some(end) if end <= size => Ok(address..end),
+            _ => Err(PyIndexError::new_err(format!(
+                "{} bytes at 0x{:X} are out of the {} bytes of memory",
+                length, address, size
+            ))),
+        }
+    }
+}
+
+#[pymethods]
+impl Emulator {
+    #[new]
+    fn new() -> Emulator {
+        Emulator {
+            chip: Chip8::headless(),
+            palette: Palette::default(),
+        }
+    }
+
+    /// Starts a program from scratch, with its settings from the ROM database
+    /// or on the platform guessed from its instructions.
+    fn load_rom(&mut self, rom: &[u8]) -> PyResult<()> {
+        let (chip, known) = Chip8::for_rom(rom).map_err(value_error)?;
+        self.chip = chip;
+        self.palette = known.and_then(|info| info.palette).unwrap_or_default();
+        Ok(())
+    }
+
+    /// Runs `frames` frames of 1/60 s, the keys stay as they are.
+    #[pyo3(signature = (frames = 1))]
+    fn step(&mut self, frames: u32) {
+        for _ in 0..frames {
+            self.chip.step_frame();
+        }
+    }
+
+    /// False once the program stopped, on an infinite loop or an exit instruction.
+    #[getter]
+    fn is_running(&self) -> bool {
+        self.chip.is_running()
+    }
+
+    fn set_key(&mut self, key: u8, is_pressed: bool) -> PyResult<()> {
+        self.chip.set_key(Self::check_key(key)?, is_pressed);
+        Ok(())
+    }
+
+    fn press(&mut self, key: u8) -> PyResult<()> {
+        self.set_key(key, true)
+    }
+
+    fn release(&mut self, key: u8) -> PyResult<()> {
+        self.set_key(key, false)
+    }
+
+    #[getter]
+    fn width(&self) -> usize {
+        self.chip.display().width()
+    }
+
+    #[getter]
+    fn height(&self) -> usize {
+        self.chip.display().height()
+    }
+
+    /// The display, height × width: the planes of each pixel (1 when lit on CHIP-8),
+    /// or height × width × 3 in the colours of the palette with `rgb`.
+    #[pyo3(signature = (rgb = false))]
+    fn framebuffer<'py>(&self, py: Python<'py>, rgb: bool) -> Bound<'py, PyAny> {
+        let display = self.chip.display();
+        let (width, height) = (display.width(), display.height());
+        if rgb {
+            let pixels: Bound<'py, PyArray3<u8>> =
+                Array3::from_shape_fn((height, width, 3), |(y, x, channel)| {
+                    self.palette.color(display.pixel(x, y))[channel]
+                })
+                .into_pyarray(py);
+            pixels.into_any()
+        } else {
+            planes(py, display).into_any()
+        }
+    }
+
+    /// A palette name or colours like `000000,FFFFFF`, as on the command line.
+    fn set_palette(&mut self, palette: &str) -> PyResult<()> {
+        self.palette = palette.parse().map_err(value_error)?;
+        Ok(())
+    }
+
+    #[getter]
+    fn memory_size(&self) -> usize {
+        self.chip.memory().len()
+    }
+
+    #[pyo3(signature = (address, length = 1))]
+    fn read_memory<'py>(
+        &self,
+        py: Python<'py>,
+        address: usize,
+        length: usize,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let range = self.memory_range(address, length)?;
+        Ok(PyBytes::new(py, &self.chip.memory()[range]))
+    }
+
+    fn write_memory(&mut self, address: usize, data: &[u8]) -> PyResult<()> {
+        let range = self.memory_range(address, data.len())?;
+        self.chip.memory_mut()[range].copy_from_slice(data);
+        Ok(())
+    }
+
+    /// The registers V0 to VF, as bytes.
+    #[getter]
+    fn v(&self) -> Vec<u8> {
+        self.chip.registers().to_vec()
+    }
+
+    #[setter]
+    fn set_v(&mut self, registers: Vec<u8>) -> PyResult<()> {
+        let count = registers.len();
+        let target = self.chip.registers_mut();
+        if count != target.len() {
+            return Err(PyValueError::new_err(format!(
+                "{} registers instead of {}",
+                count,
+                target.len()
+            )));
+        }
+        target.copy_from_slice(&registers);
+        Ok(())
+    }
+
+    fn set_register(&mut self, register: usize, value: u8) -> PyResult<()> {
+        let target = self
+            .chip
+            .registers_mut()
+            .get_mut(register)
+            .ok_or_else(|| PyIndexError::new_err(format!("no register V{:X}", register)))?;
+        *target = value;
+        Ok(())
+    }
+
+    /// The address register.
+    #[getter]
+    fn i(&self) -> usize {
+        self.chip.index()
+    }
+
+    #[setter]
+    fn set_i(&mut self, address: usize) {
+        self.chip.set_index(address);
+    }
+
+    #[getter]
+    fn pc(&self) -> usize {
+        self.chip.pc()
+    }
+
+    #[setter]
+    fn set_pc(&mut self, address: usize) {
+        self.chip.set_pc(address);
+    }
+
+    #[getter]
+    fn delay_timer(&self) -> u8 {
+        self.chip.timers().0
+    }
+
+    #[setter]
+    fn set_delay_timer(&mut self, delay: u8) {
+        let (_, sound) = self.chip.timers();
+        self.chip.set_timers(delay, sound);
+    }
+
+    #[getter]
+    fn sound_timer(&self) -> u8 {
+        self.chip.timers().1
+    }
+
+    #[setter]
+    fn set_sound_timer(&mut self, sound: u8) {
+        let (delay, _) = self.chip.timers();
+        self.chip.set_timers(delay, sound);
+    }
+
+    #[getter]
+    fn is_sound_playing(&self) -> bool {
+        self.chip.is_sound_playing()
+    }
+
+    /// Instructions executed per frame.
+    fn set_speed(&mut self, cycles_per_frame: u32) {
+        self.chip.set_speed(cycles_per_frame);
+    }
+
+    /// Makes `CXNN` draw the same numbers at every run.
+    fn set_seed(&mut self, seed: u64) {
+        self.chip.set_seed(seed);
+    }
+
+    fn save_state<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.chip.save_state())
+    }
+
+    /// Puts the machine back in a state made by `save_state`.
+    fn load_state(&mut self, state: &[u8]) -> PyResult<()> {
+        self.chip.load_state(state).map_err(value_error)
+    }
+}
+
+/// A Gym-style environment around a ROM, the observations are the planes of the pixels.
+///
+/// ```python
+/// env = chip_huit.Environment(rom, open("game.toml").read())
+/// observation = env.reset(seed=1)
+/// observation, reward, done = env.step(2)
+/// ```
+#[pyclass(unsendable)]
+pub struct Environment {
+    environment: environment::Environment,
+}
+
+#[pymethods]
+impl Environment {
+    /// `spec` is the TOML of a game definition, without it the episodes
+    /// only end with the program.
+    #[new]
+    #[pyo3(signature = (rom, spec = None, frame_skip = None))]
+    fn new(rom: &[u8], spec: Option<&str>, frame_skip: Option<u32>) -> PyResult<Environment> {
+        let spec = match spec {
+            Some(spec) => spec.parse().map_err(value_error)?,
+            None => GameSpec::default(),
+        };
+        let mut environment = environment::Environment::new(rom, spec).map_err(value_error)?;
+        if let Some(frame_skip) = frame_skip {
+            environment.set_frame_skip(frame_skip);
+        }
+        Ok(Environment { environment })
+    }
+
+    #[getter]
+    fn action_count(&self) -> usize {
+        self.environment.action_count()
+    }
+
+    #[pyo3(signature = (seed = None))]
+    fn reset<'py>(
+        &mut self,
+        py: Python<'py>,
+        seed: Option<u64>,
+    ) -> PyResult<Bound<'py, PyArray2<u8>>> {
+        let display = self.environment.reset(seed).map_err(value_error)?;
+        Ok(planes(py, display))
+    }
+
+    /// Gives the observation, the reward and whether the episode is over.
+    fn step<'py>(
+        &mut self,
+        py: Python<'py>,
+        action: usize,
+    ) -> PyResult<(Bound<'py, PyArray2<u8>>, f64, bool)> {
+        let (display, reward, is_done) = self.environment.step(action).map_err(value_error)?;
+        Ok((planes(py, display), reward, is_done))
+    }
+}
+
+#[pymodule]
+fn chip_huit(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<Emulator>()?;
+    module.add_class::<Environment>()
+}
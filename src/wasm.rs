@@ -0,0 +1,112 @@
+//! The JavaScript API of the WebAssembly build, `web/index.html` shows how to use it.
+//!
+//! ```text
+//! cargo rustc --release --lib --crate-type cdylib --target wasm32-unknown-unknown --no-default-features --features wasm
+//! wasm-bindgen --target web --out-dir web/pkg target/wasm32-unknown-unknown/release/chip_huit.wasm
+//! ```
+
+use wasm_bindgen::prelude::*;
+
+use crate::chip8::graphic_engine::{Keymap, Palette};
+use crate::chip8::headless_interface::HeadlessInterface;
+use crate::chip8::Chip8;
+
+/// An emulator driven by the page, which calls `step_frame` 60 times per second.
+#[wasm_bindgen]
+pub struct Emulator {
+    chip: Chip8<HeadlessInterface>,
+    palette: Palette,
+    keymap: Keymap,
+}
+
+impl Default for Emulator {
+    fn default() -> Emulator {
+        Emulator::new()
+    }
+}
+
+#[wasm_bindgen]
+impl Emulator {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Emulator {
+        Emulator {
+            chip: Chip8::headless(),
+            palette: Palette::default(),
+            keymap: Keymap::default(),
+        }
+    }
+
+    /// Starts a program from scratch, with its settings from the ROM database
+    /// or on the platform guessed from its instructions.
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), JsValue> {
+        let (chip, known) = Chip8::for_rom(rom).map_err(|error| JsValue::from_str(&error))?;
+
+        self.chip = chip;
+        self.palette = known
+            .as_ref()
+            .and_then(|info| info.palette)
+            .unwrap_or_default();
+        self.keymap = known.and_then(|info| info.keymap).unwrap_or_default();
+        Ok(())
+    }
+
+    pub fn step_frame(&mut self) {
+        self.chip.step_frame();
+    }
+
+    pub fn width(&self) -> usize {
+        self.chip.display().width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.chip.display().height()
+    }
+
+    /// The display in RGBA, row by row, ready for an `ImageData`.
+    pub fn framebuffer(&self) -> Vec<u8> {
+        let display = self.chip.display();
+        (0..display.height())
+            .flat_map(|y| (0..display.width()).map(move |x| display.pixel(x, y)))
+            .flat_map(|planes| {
+                let [r, g, b] = self.palette.color(planes);
+                vec![r, g, b, 0xFF]
+            })
+            .collect()
+    }
+
+    /// Presses or releases the keypad key 0 to F.
+    pub fn set_key(&mut self, key: u8, is_pressed: bool) {
+        self.chip.set_key(key, is_pressed);
+    }
+
+    /// The keypad key bound to a keyboard key, for the program running.
+    pub fn keypad_key(&self, keyboard_key: char) -> Option<u8> {
+        self.keymap.key(keyboard_key)
+    }
+
+    pub fn is_sound_playing(&self) -> bool {
+        self.chip.is_sound_playing()
+    }
+
+    /// The 128 bits of the sound, played at 4000 * 2^((pitch - 64) / 48) bits per second.
+    pub fn audio_pattern(&self) -> Vec<u8> {
+        self.chip.audio_pattern().to_vec()
+    }
+
+    pub fn pitch(&self) -> u8 {
+        self.chip.pitch()
+    }
+
+    /// A palette name or colours like `000000,FFFFFF`, as on the command line.
+    pub fn set_palette(&mut self, palette: &str) -> Result<(), JsValue> {
+        self.palette = palette
+            .parse()
+            .map_err(|error: String| JsValue::from_str(&error))?;
+        Ok(())
+    }
+
+    /// Instructions executed per frame.
+    pub fn set_speed(&mut self, cycles_per_frame: u32) {
+        self.chip.set_speed(cycles_per_frame);
+    }
+}